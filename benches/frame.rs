@@ -0,0 +1,112 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use goridge_rs::buffer::BufferPool;
+use goridge_rs::frame::Frame;
+use goridge_rs::frame::frame_flags::Flag;
+use goridge_rs::relay::FrameReader;
+use std::hint::black_box;
+use std::sync::Arc;
+
+const PAYLOAD_SIZES: &[usize] = &[0, 64, 1024, 64 * 1024, 1024 * 1024];
+const OPTIONS_COUNTS: &[usize] = &[0, 5, 10];
+// frames read by the stream reader per iteration
+const STREAM_FRAMES: usize = 16;
+
+fn encode(options: &[u32], payload: &[u8]) -> Vec<u8> {
+    let mut frame = Frame::default();
+    frame.write_version(1);
+    frame.write_flags(&[Flag::CodecRaw]);
+    if !options.is_empty() {
+        frame.write_options(options).unwrap();
+    }
+    frame.write_payload(payload);
+    frame.write_crc();
+    frame.bytes()
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+
+    for &size in PAYLOAD_SIZES {
+        let payload = vec![b'a'; size];
+        group.throughput(Throughput::Bytes(size as u64));
+
+        for &count in OPTIONS_COUNTS {
+            let options: Vec<u32> = (0..count as u32).collect();
+            group.bench_with_input(
+                BenchmarkId::new(format!("options_{}", count), size),
+                &payload,
+                |b, payload| b.iter(|| encode(black_box(&options), black_box(payload))),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+
+    for &size in PAYLOAD_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+
+        for &count in OPTIONS_COUNTS {
+            let options: Vec<u32> = (0..count as u32).collect();
+            let data = encode(&options, &vec![b'a'; size]);
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("options_{}", count), size),
+                &data,
+                |b, data| {
+                    b.iter(|| {
+                        let (frame, _) = Frame::decode(black_box(data)).unwrap().unwrap();
+                        frame.verify_crc().unwrap();
+                        frame
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_stream_reader(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("stream_reader");
+
+    for &size in PAYLOAD_SIZES {
+        let data = encode(&[1, 2], &vec![b'a'; size]).repeat(STREAM_FRAMES);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("unpooled", size), &data, |b, data| {
+            b.iter(|| {
+                rt.block_on(async {
+                    let mut reader = FrameReader::new(data.as_slice());
+                    for _ in 0..STREAM_FRAMES {
+                        black_box(reader.read_frame().await.unwrap());
+                    }
+                })
+            })
+        });
+
+        let pool = Arc::new(BufferPool::default());
+        group.bench_with_input(BenchmarkId::new("pooled", size), &data, |b, data| {
+            b.iter(|| {
+                rt.block_on(async {
+                    let mut reader = FrameReader::new(data.as_slice());
+                    reader.set_buffer_pool(pool.clone());
+                    for _ in 0..STREAM_FRAMES {
+                        black_box(reader.read_frame().await.unwrap());
+                    }
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_decode, bench_stream_reader);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+#![no_main]
+
+use goridge_rs::frame::Frame;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut frame) = Frame::default().read_frame(data) {
+        _ = frame.verify_crc();
+        _ = frame.verify_payload_crc();
+        _ = frame.read_header();
+        _ = frame.read_options();
+        _ = frame.read_payload_len();
+        _ = frame.bytes();
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use goridge_rs::frame::Frame;
+use goridge_rs::frame::header::HEADER_LEN;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // header shorter than 12 bytes could not be produced by the decoder
+    if data.len() < HEADER_LEN {
+        return;
+    }
+
+    let mut frame = Frame::default();
+    *frame.header_mut() = data.to_vec();
+
+    _ = frame.read_options();
+    _ = frame.read_header();
+});
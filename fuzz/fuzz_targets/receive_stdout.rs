@@ -0,0 +1,25 @@
+#![no_main]
+
+use goridge_rs::relay::FrameReader;
+use libfuzzer_sys::fuzz_target;
+use tokio::io::AsyncWriteExt;
+
+fuzz_target!(|data: &[u8]| {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+
+    rt.block_on(async {
+        let (mut tx, rx) = tokio::io::duplex(data.len() + 1);
+        tx.write_all(data).await.unwrap();
+        // EOF after the data, so the reader doesn't wait for more
+        drop(tx);
+
+        let mut reader = FrameReader::new(rx);
+        // don't let the fuzzer allocate gigabytes for the payload
+        reader.set_max_payload_len(1024 * 1024);
+
+        while reader.read_frame().await.is_ok() {}
+    });
+});
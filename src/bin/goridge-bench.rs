@@ -0,0 +1,175 @@
+// Load test: drives N concurrent workers with the fixed size payloads and reports
+// the throughput and latency percentiles.
+//
+// usage: goridge-bench [--workers N] [--requests N] [--payload BYTES] -- COMMAND [ARGS...]
+//
+// Every worker is sent `--requests` jobs and is expected to echo the payload back,
+// e.g. `goridge-bench --workers 8 -- goridge-echo-worker`.
+
+use goridge_rs::frame::Frame;
+use goridge_rs::frame::frame_flags::Flag;
+use goridge_rs::pipe::Pipes;
+use goridge_rs::pipe::commands::StopCommand;
+use goridge_rs::replay::percentile;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+struct Config {
+    workers: usize,
+    requests: usize,
+    payload: usize,
+    cmd: Vec<String>,
+}
+
+fn parse_args() -> anyhow::Result<Config> {
+    let mut cfg = Config {
+        workers: 4,
+        requests: 10_000,
+        payload: 1024,
+        cmd: vec![],
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+
+        let value = match args.next() {
+            Some(value) => value,
+            None => anyhow::bail!("no value for the {} argument", arg),
+        };
+
+        match arg.as_str() {
+            "--workers" => cfg.workers = value.parse()?,
+            "--requests" => cfg.requests = value.parse()?,
+            "--payload" => cfg.payload = value.parse()?,
+            _ => anyhow::bail!("unknown argument: {}", arg),
+        }
+    }
+
+    cfg.cmd = args.collect();
+    if cfg.cmd.is_empty() {
+        anyhow::bail!(
+            "no worker command, usage: goridge-bench [--workers N] [--requests N] [--payload BYTES] -- COMMAND [ARGS...]"
+        );
+    }
+
+    Ok(cfg)
+}
+
+async fn run_worker(
+    cmd: Vec<String>,
+    requests: usize,
+    payload: usize,
+) -> anyhow::Result<Vec<Duration>> {
+    let cmd: Vec<&str> = cmd.iter().map(String::as_str).collect();
+    let mut pipes = Pipes::new(&cmd).await?;
+    pipes.send_pid().await?;
+
+    let payload = vec![b'a'; payload];
+    let mut latencies = Vec::with_capacity(requests);
+
+    for _ in 0..requests {
+        let mut frame = Frame::default();
+        frame.write_version(pipes.version());
+        frame.write_flags(&[Flag::CodecRaw]);
+        frame.write_payload(&payload);
+        frame.write_crc();
+
+        let start = Instant::now();
+        pipes.send(&mut frame).await?;
+        let response = pipes.receive_stdout().await?;
+        latencies.push(start.elapsed());
+
+        if response.payload().len() != payload.len() {
+            anyhow::bail!(
+                "unexpected response payload len {}, sent {}",
+                response.payload().len(),
+                payload.len()
+            );
+        }
+    }
+
+    pipes.send_control(StopCommand::default()).await?;
+    pipes.wait().await?;
+
+    Ok(latencies)
+}
+
+fn micros(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{:.1}us", latency.as_secs_f64() * 1_000_000.0),
+        None => "-".to_string(),
+    }
+}
+
+// power of two buckets in microseconds
+fn print_histogram(latencies: &[Duration]) {
+    let mut buckets: Vec<usize> = vec![];
+    for latency in latencies {
+        let us = latency.as_micros().max(1) as u64;
+        let bucket = (u64::BITS - us.leading_zeros() - 1) as usize;
+        if buckets.len() <= bucket {
+            buckets.resize(bucket + 1, 0);
+        }
+        buckets[bucket] += 1;
+    }
+
+    let max = buckets.iter().copied().max().unwrap_or(0).max(1);
+    let first = buckets.iter().position(|&n| n > 0).unwrap_or(0);
+
+    for (bucket, &count) in buckets.iter().enumerate().skip(first) {
+        println!(
+            "  {:>10} .. {:<10} {:>8} {}",
+            format!("{}us", 1u64 << bucket),
+            format!("{}us", 1u64 << (bucket + 1)),
+            count,
+            "#".repeat(count * 50 / max)
+        );
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let cfg = parse_args()?;
+
+    let start = Instant::now();
+    let mut tasks = JoinSet::new();
+    for _ in 0..cfg.workers {
+        tasks.spawn(run_worker(cfg.cmd.clone(), cfg.requests, cfg.payload));
+    }
+
+    let mut latencies = vec![];
+    while let Some(res) = tasks.join_next().await {
+        latencies.extend(res??);
+    }
+    let elapsed = start.elapsed();
+
+    let total = latencies.len();
+    let rps = total as f64 / elapsed.as_secs_f64();
+    // payload is sent and received back
+    let mbps = rps * (cfg.payload * 2) as f64 / (1024.0 * 1024.0);
+
+    println!(
+        "{} workers, {} requests, {} bytes payload in {:.3}s",
+        cfg.workers,
+        total,
+        cfg.payload,
+        elapsed.as_secs_f64()
+    );
+    println!("throughput: {:.0} req/s, {:.2} MiB/s", rps, mbps);
+
+    for (name, p) in [("p50", 0.5), ("p99", 0.99), ("p999", 0.999), ("max", 1.0)] {
+        println!(
+            "latency {}: {}",
+            name,
+            micros(percentile(&mut latencies, p))
+        );
+    }
+
+    println!("histogram:");
+    print_histogram(&latencies);
+
+    Ok(())
+}
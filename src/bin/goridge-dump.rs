@@ -0,0 +1,320 @@
+// Decodes a stream of goridge frames and pretty-prints them.
+//
+// usage: goridge-dump [--preview N] [--capture] [FILE]
+//
+// Reads STDIN when FILE is missing or `-`. With `--capture` the input is a capture file
+// recorded by goridge-tap, both directions are decoded in the recorded order.
+// Exits with 1 if the stream has corrupted data.
+
+use goridge_rs::capture::{CaptureReader, Direction};
+use goridge_rs::frame::Frame;
+use goridge_rs::frame::frame_flags::Flags;
+use goridge_rs::frame::header::{FrameHeader, HEADER_LEN};
+use std::io::{Read, Write};
+use std::time::UNIX_EPOCH;
+
+const DEFAULT_PREVIEW: usize = 256;
+
+struct Dumper<W: Write> {
+    out: W,
+    buf: Vec<u8>,
+    // stream offset of the buf[0]
+    offset: usize,
+    frames: usize,
+    preview: usize,
+    // looking for the next valid header after the corrupted data
+    resyncing: bool,
+    // garbage already dropped while resyncing and its first bytes for the preview
+    skipped: usize,
+    skipped_head: Vec<u8>,
+    corrupted: bool,
+}
+
+impl<W: Write> Dumper<W> {
+    fn new(out: W, preview: usize) -> Self {
+        Dumper {
+            out,
+            buf: vec![],
+            offset: 0,
+            frames: 0,
+            preview,
+            resyncing: false,
+            skipped: 0,
+            skipped_head: vec![],
+            corrupted: false,
+        }
+    }
+
+    fn feed(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.buf.extend_from_slice(data);
+        self.process(false)
+    }
+
+    // records are cut at the frame boundaries, every record is decoded up to the end
+    fn feed_record(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.buf.extend_from_slice(data);
+        self.process(true)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.process(true)?;
+        writeln!(self.out, "{} frames", self.frames)
+    }
+
+    fn process(&mut self, eof: bool) -> std::io::Result<()> {
+        while !self.buf.is_empty() {
+            if self.resyncing {
+                if !self.resync(eof)? {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            // garbage is detected by the header CRC before waiting for the whole frame
+            if let Some(header) = self.buf.get(..HEADER_LEN) {
+                let header: &[u8; HEADER_LEN] = header.try_into().unwrap();
+                if let Err(err) = FrameHeader::parse(header).and_then(|h| h.verify_crc()) {
+                    self.corruption(&err.to_string())?;
+                    continue;
+                }
+            }
+
+            match Frame::decode(&self.buf) {
+                Ok(Some((frame, n))) => {
+                    self.print_frame(&frame)?;
+                    self.consume(n);
+                }
+                Ok(None) => {
+                    if eof {
+                        self.corrupted = true;
+                        writeln!(
+                            self.out,
+                            "truncated frame at offset {}: stream ended after {} bytes",
+                            self.offset,
+                            self.buf.len()
+                        )?;
+                        self.consume(self.buf.len());
+                    }
+
+                    return Ok(());
+                }
+                Err(err) => self.corruption(&err.to_string())?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.buf.drain(..n);
+        self.offset += n;
+    }
+
+    fn corruption(&mut self, reason: &str) -> std::io::Result<()> {
+        self.corrupted = true;
+        self.resyncing = true;
+        writeln!(
+            self.out,
+            "corrupted data at offset {}: {}",
+            self.offset, reason
+        )
+    }
+
+    // skips the bytes up to the next valid header, false if more data is needed
+    fn resync(&mut self, eof: bool) -> std::io::Result<bool> {
+        // buf[0] is the corrupted header until something is skipped
+        let from = match self.skipped {
+            0 => 1,
+            _ => 0,
+        };
+        let found = (from..(self.buf.len() + 1).saturating_sub(HEADER_LEN)).find(|&i| {
+            let header: &[u8; HEADER_LEN] = self.buf[i..i + HEADER_LEN].try_into().unwrap();
+            FrameHeader::parse(header).is_ok_and(|h| h.verify_crc().is_ok())
+        });
+
+        let skip = match (found, eof) {
+            (Some(i), _) => i,
+            (None, true) => self.buf.len(),
+            (None, false) => {
+                // the tail could be the beginning of the header, the rest is dropped right away
+                let skip = self.buf.len().saturating_sub(HEADER_LEN - 1);
+                self.skip(skip);
+                return Ok(false);
+            }
+        };
+
+        self.skip(skip);
+        let skipped = preview_of(&self.skipped_head, self.skipped, self.preview);
+        writeln!(self.out, "  skipped {} bytes: {}", self.skipped, skipped)?;
+        self.skipped = 0;
+        self.skipped_head.clear();
+        self.resyncing = false;
+
+        Ok(true)
+    }
+
+    fn skip(&mut self, n: usize) {
+        let head = n.min(self.preview.saturating_sub(self.skipped_head.len()));
+        self.skipped_head.extend_from_slice(&self.buf[..head]);
+        self.skipped += n;
+        self.consume(n);
+    }
+
+    fn print_frame(&mut self, frame: &Frame) -> std::io::Result<()> {
+        let flags = frame.read_flags();
+        let options: Vec<u32> = frame.options().collect();
+        let crc = u32::from_le_bytes(frame.header()[6..10].try_into().unwrap());
+        let checked = match frame.verify_crc() {
+            Ok(_) => "valid",
+            Err(_) => "invalid",
+        };
+
+        writeln!(self.out, "frame #{} at offset {}", self.frames, self.offset)?;
+        writeln!(self.out, "  version: {}", frame.version())?;
+        writeln!(self.out, "  flags: {:?}", flags)?;
+        writeln!(self.out, "  header len: {} words", frame.read_hl())?;
+        writeln!(self.out, "  options: {:?}", options)?;
+        writeln!(self.out, "  payload len: {}", frame.read_payload_len())?;
+        writeln!(self.out, "  crc: {:#010x} ({})", crc, checked)?;
+
+        let payload = frame.payload();
+        if !payload.is_empty() {
+            let pretty = match flags.contains(Flags::CODEC_JSON) {
+                true => serde_json::from_slice::<serde_json::Value>(payload)
+                    .ok()
+                    .and_then(|v| serde_json::to_string_pretty(&v).ok()),
+                false => None,
+            };
+
+            match pretty {
+                Some(json) => writeln!(self.out, "  payload:\n{}", indent(&json))?,
+                None => writeln!(self.out, "  payload: {}", preview(payload, self.preview))?,
+            }
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+fn preview(data: &[u8], limit: usize) -> String {
+    preview_of(data, data.len(), limit)
+}
+
+// preview of `len` bytes starting with `head`
+fn preview_of(head: &[u8], len: usize, limit: usize) -> String {
+    let shown = &head[..head.len().min(limit)];
+    let mut s = format!("\"{}\"", String::from_utf8_lossy(shown).escape_debug());
+    if len > shown.len() {
+        s.push_str(&format!(" ... ({} more bytes)", len - shown.len()));
+    }
+    s
+}
+
+fn indent(s: &str) -> String {
+    s.lines()
+        .map(|l| format!("    {}", l))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn dump_capture<R: Read>(input: R, preview: usize) -> anyhow::Result<bool> {
+    let mut reader = CaptureReader::new(input)?;
+    // every direction is a separate stream with its own offsets
+    let mut to_worker = Dumper::new(std::io::stdout(), preview);
+    let mut from_worker = Dumper::new(std::io::stdout(), preview);
+    let mut started = None;
+    let mut records = 0;
+
+    while let Some(record) = reader.read_record()? {
+        let started = *started.get_or_insert_with(|| {
+            let since_epoch = record
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            println!(
+                "capture started at {}.{:06}",
+                since_epoch.as_secs(),
+                since_epoch.subsec_micros()
+            );
+            record.timestamp
+        });
+
+        let elapsed = record.timestamp.duration_since(started).unwrap_or_default();
+        let (dumper, direction) = match record.direction {
+            Direction::ToWorker => (&mut to_worker, "parent -> worker"),
+            Direction::FromWorker => (&mut from_worker, "worker -> parent"),
+        };
+
+        println!(
+            "record #{} at +{:.6}s: {}, {} bytes",
+            records,
+            elapsed.as_secs_f64(),
+            direction,
+            record.data.len()
+        );
+        dumper.feed_record(&record.data)?;
+        records += 1;
+    }
+
+    println!(
+        "{} frames ({} parent -> worker, {} worker -> parent)",
+        to_worker.frames + from_worker.frames,
+        to_worker.frames,
+        from_worker.frames
+    );
+
+    Ok(to_worker.corrupted || from_worker.corrupted)
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut preview = DEFAULT_PREVIEW;
+    let mut capture = false;
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preview" => match args.next() {
+                Some(value) => preview = value.parse()?,
+                None => anyhow::bail!("no value for the --preview argument"),
+            },
+            "--capture" => capture = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => anyhow::bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    let mut input: Box<dyn Read> = match path.as_deref() {
+        None | Some("-") => Box::new(std::io::stdin().lock()),
+        Some(path) => Box::new(std::fs::File::open(path)?),
+    };
+
+    if capture {
+        if dump_capture(input, preview)? {
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    let mut dumper = Dumper::new(std::io::stdout().lock(), preview);
+    let mut chunk = vec![0; 64 * 1024];
+
+    loop {
+        let n = input.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+
+        dumper.feed(&chunk[..n])?;
+    }
+
+    dumper.finish()?;
+
+    if dumper.corrupted {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,268 @@
+// Worker implementing the goridge worker protocol for the integration tests:
+// replies to the PID handshake, stops on the stop command and echoes every job back.
+//
+// usage: goridge-echo-worker [--start-delay-ms N] [--delay-ms N] [--delay-jobs N]
+//                            [--error-every N] [--noise-every N] [--grow-bytes N]
+//                            [--exit-after N] [--exit-code N] [--exit-stderr N] [--seed N]
+//                            [--integrity none|header|payload] [--legacy-control 1]
+//                            [--crash-marker PATH] [--crash-once PATH] [--start-gate DIR]
+
+use goridge_rs::frame::Frame;
+use goridge_rs::frame::frame_flags::{Flag, Flags};
+use goridge_rs::frame::integrity::Integrity;
+use goridge_rs::pipe::commands::{CancelCommand, ControlCommand};
+use goridge_rs::relay::StreamRelay;
+use std::collections::VecDeque;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, sleep};
+
+#[derive(Default)]
+struct Config {
+    // delay before the STDIN is read, a slow booting worker
+    start_delay: Duration,
+    // delay before every response
+    delay: Duration,
+    // only the first N jobs are delayed, 0 for all of them
+    delay_jobs: u64,
+    // every Nth job is answered with the ERROR flag
+    error_every: u64,
+    // every Nth response is preceded by random bytes on STDOUT
+    noise_every: u64,
+    // bytes leaked on every job
+    grow_bytes: usize,
+    // exit without the response on the Nth job
+    exit_after: u64,
+    exit_code: i32,
+    // lines written to STDERR before the exit
+    exit_stderr: u64,
+    seed: u64,
+    integrity: Integrity,
+    // the unknown control commands (cancel) are fatal like in the RoadRunner PHP worker:
+    // the job in flight is finished, then the worker exits
+    legacy_control: bool,
+    // the first start creates the file, every later start finds it and exits before the handshake
+    crash_marker: Option<String>,
+    // the first start creates the file and exits before the handshake, every later start serves
+    crash_once: Option<String>,
+    // the start is registered in the directory as the file named by the PID, the handshake
+    // waits until the `open` file or the `open-PID` file is created there
+    start_gate: Option<String>,
+}
+
+fn parse_args() -> anyhow::Result<Config> {
+    let mut cfg = Config {
+        seed: 0x9E37_79B9_7F4A_7C15,
+        ..Default::default()
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match args.next() {
+            Some(value) => value,
+            None => anyhow::bail!("no value for the {} argument", arg),
+        };
+
+        match arg.as_str() {
+            "--start-delay-ms" => cfg.start_delay = Duration::from_millis(value.parse()?),
+            "--delay-ms" => cfg.delay = Duration::from_millis(value.parse()?),
+            "--delay-jobs" => cfg.delay_jobs = value.parse()?,
+            "--error-every" => cfg.error_every = value.parse()?,
+            "--noise-every" => cfg.noise_every = value.parse()?,
+            "--grow-bytes" => cfg.grow_bytes = value.parse()?,
+            "--exit-after" => cfg.exit_after = value.parse()?,
+            "--exit-code" => cfg.exit_code = value.parse()?,
+            "--exit-stderr" => cfg.exit_stderr = value.parse()?,
+            "--seed" => cfg.seed = value.parse()?,
+            "--legacy-control" => cfg.legacy_control = value != "0",
+            "--crash-marker" => cfg.crash_marker = Some(value),
+            "--crash-once" => cfg.crash_once = Some(value),
+            "--start-gate" => cfg.start_gate = Some(value),
+            "--integrity" => {
+                cfg.integrity = match value.as_str() {
+                    "none" => Integrity::None,
+                    "header" => Integrity::Header,
+                    "payload" => Integrity::HeaderPayload,
+                    _ => anyhow::bail!("unknown integrity mode: {}", value),
+                }
+            }
+            _ => anyhow::bail!("unknown argument: {}", arg),
+        }
+    }
+
+    Ok(cfg)
+}
+
+// xorshift64, deterministic for the given seed
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[inline]
+fn every(n: u64, job: u64) -> bool {
+    n > 0 && job.is_multiple_of(n)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let cfg = parse_args()?;
+
+    if let Some(marker) = &cfg.crash_marker {
+        if std::path::Path::new(marker).exists() {
+            eprintln!("crashed on the start, {} exists", marker);
+            std::process::exit(1);
+        }
+        std::fs::write(marker, b"")?;
+    }
+
+    if let Some(marker) = &cfg.crash_once
+        && !std::path::Path::new(marker).exists()
+    {
+        std::fs::write(marker, b"")?;
+        eprintln!("crashed on the first start, {} is created", marker);
+        std::process::exit(1);
+    }
+
+    if let Some(gate) = &cfg.start_gate {
+        wait_gate(std::path::Path::new(gate)).await?;
+    }
+
+    serve(cfg).await?;
+
+    // the runtime shutdown would wait for the STDIN read in the background
+    std::process::exit(0)
+}
+
+async fn wait_gate(dir: &std::path::Path) -> anyhow::Result<()> {
+    let pid = std::process::id();
+    std::fs::write(dir.join(pid.to_string()), b"")?;
+
+    while !dir.join("open").exists() && !dir.join(format!("open-{}", pid)).exists() {
+        sleep(Duration::from_millis(5)).await;
+    }
+    Ok(())
+}
+
+async fn serve(cfg: Config) -> anyhow::Result<()> {
+    let mut rng = cfg.seed.max(1);
+    let mut leaked: Vec<Vec<u8>> = vec![];
+    let mut jobs: u64 = 0;
+
+    // STDIN is read in the background, the cancel command arrives while the job is running
+    let mut input = StreamRelay::new(tokio::io::stdin(), tokio::io::sink());
+    input.set_integrity(cfg.integrity);
+    let mut relay = StreamRelay::new(tokio::io::empty(), tokio::io::stdout());
+    relay.set_integrity(cfg.integrity);
+
+    if !cfg.start_delay.is_zero() {
+        sleep(cfg.start_delay).await;
+    }
+
+    let (tx, mut rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        while let Ok(frame) = input.receive().await {
+            if tx.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // received during the job, served after it like a single-threaded worker does
+    let mut deferred = VecDeque::new();
+
+    loop {
+        let frame = match deferred.pop_front() {
+            Some(frame) => frame,
+            None => match rx.recv().await {
+                Some(frame) => frame,
+                // parent closed STDIN
+                None => return Ok(()),
+            },
+        };
+
+        if frame.read_flags().contains(Flags::CONTROL) {
+            match ControlCommand::parse(&frame)? {
+                ControlCommand::Pid(request) => {
+                    relay.respond_pid(&request, std::process::id()).await?
+                }
+                ControlCommand::Stop => return Ok(()),
+                ControlCommand::Cancel if cfg.legacy_control => {
+                    eprintln!("undefined control package");
+                    std::process::exit(1);
+                }
+                // the job is done already
+                ControlCommand::Cancel => relay.send_control(CancelCommand::default()).await?,
+            }
+
+            continue;
+        }
+
+        jobs += 1;
+
+        if cfg.exit_after > 0 && jobs >= cfg.exit_after {
+            for line in 1..=cfg.exit_stderr {
+                eprintln!("fatal error {} on the job {}", line, jobs);
+            }
+            std::process::exit(cfg.exit_code);
+        }
+
+        if cfg.grow_bytes > 0 {
+            leaked.push(vec![1; cfg.grow_bytes]);
+        }
+
+        if !cfg.delay.is_zero() && (cfg.delay_jobs == 0 || jobs <= cfg.delay_jobs) {
+            let delay = sleep(cfg.delay);
+            tokio::pin!(delay);
+
+            let mut cancelled = false;
+            while !cancelled {
+                tokio::select! {
+                    _ = &mut delay => break,
+                    next = rx.recv() => {
+                        let Some(next) = next else {
+                            return Ok(());
+                        };
+                        let cancel = next.read_flags().contains(Flags::CONTROL)
+                            && matches!(ControlCommand::parse(&next), Ok(ControlCommand::Cancel));
+                        match cancel && !cfg.legacy_control {
+                            true => cancelled = true,
+                            false => deferred.push_back(next),
+                        }
+                    }
+                }
+            }
+
+            // aborted, only the acknowledgement is sent
+            if cancelled {
+                relay.send_control(CancelCommand::default()).await?;
+                continue;
+            }
+        }
+
+        if every(cfg.noise_every, jobs) {
+            let len = 16 + next_random(&mut rng) as usize % 48;
+            let noise: Vec<u8> = (0..len).map(|_| next_random(&mut rng) as u8).collect();
+            relay.writer_mut().write_all(&noise).await?;
+        }
+
+        let mut response = Frame::default();
+        response.write_version(relay.version());
+
+        if every(cfg.error_every, jobs) {
+            response.write_flags(&[Flag::Error]);
+            response.write_payload(format!("injected error on the job {}", jobs).as_bytes());
+        } else {
+            response.set_flags(frame.read_flags());
+            if let Some(options) = frame.read_options() {
+                response.write_options(&options)?;
+            }
+            response.write_payload(frame.payload());
+        }
+
+        response.write_crc();
+        relay.send(&mut response).await?;
+    }
+}
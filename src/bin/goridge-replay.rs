@@ -0,0 +1,100 @@
+// Replays the recorded requests against a freshly spawned worker and compares the responses
+// with the recorded ones.
+//
+// usage: goridge-replay [--verbose] FILE -- COMMAND [ARGS...]
+//
+// FILE is a goridge-tap capture or the concatenated request frames.
+// Exits with 1 if any response differs from the recorded one.
+
+use goridge_rs::pipe::Pipes;
+use goridge_rs::pipe::commands::StopCommand;
+use goridge_rs::replay::{Report, load, replay};
+use std::time::Duration;
+
+fn parse_args() -> anyhow::Result<(bool, String, Vec<String>)> {
+    let mut verbose = false;
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    for arg in args.by_ref() {
+        match arg.as_str() {
+            "--verbose" => verbose = true,
+            "--" => break,
+            _ if path.is_none() => path = Some(arg),
+            _ => anyhow::bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    let usage = "usage: goridge-replay [--verbose] FILE -- COMMAND [ARGS...]";
+    let Some(path) = path else {
+        anyhow::bail!("no capture file, {}", usage);
+    };
+
+    let cmd: Vec<String> = args.collect();
+    if cmd.is_empty() {
+        anyhow::bail!("no worker command, {}", usage);
+    }
+
+    Ok((verbose, path, cmd))
+}
+
+fn format_latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{:.3}ms", latency.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+fn print_report(report: &Report, verbose: bool) {
+    for (idx, result) in report.results.iter().enumerate() {
+        if verbose || !result.differences.is_empty() {
+            println!(
+                "exchange #{}: {} (recorded {})",
+                idx,
+                format_latency(Some(result.latency)),
+                format_latency(result.recorded_latency)
+            );
+        }
+
+        for difference in &result.differences {
+            println!("  {}", difference);
+        }
+    }
+
+    println!(
+        "{} exchanges, {} mismatches",
+        report.results.len(),
+        report.mismatches()
+    );
+
+    for (name, p) in [("p50", 0.5), ("p99", 0.99), ("max", 1.0)] {
+        println!(
+            "latency {}: {} (recorded {})",
+            name,
+            format_latency(report.latency_percentile(p)),
+            format_latency(report.recorded_latency_percentile(p))
+        );
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let (verbose, path, cmd) = parse_args()?;
+    let exchanges = load(&std::fs::read(&path)?)?;
+
+    let cmd: Vec<&str> = cmd.iter().map(String::as_str).collect();
+    let mut pipes = Pipes::new(&cmd).await?;
+    pipes.send_pid().await?;
+
+    let report = replay(&mut pipes, &exchanges).await?;
+    print_report(&report, verbose);
+
+    pipes.send_control(StopCommand::default()).await?;
+    pipes.wait().await?;
+
+    if report.mismatches() > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+// Transparent proxy between the parent and the worker: spawns the worker command,
+// forwards STDIN/STDOUT unchanged and records every frame crossing the pipe.
+//
+// usage: goridge-tap --capture FILE [--max-payload-len BYTES] -- COMMAND [ARGS...]
+//
+// Frames with the payload bigger than --max-payload-len (64MB by default) are recorded as garbage.
+// The capture file can be decoded with `goridge-dump --capture FILE`.
+// Exits with the worker exit code.
+
+use goridge_rs::capture::{
+    CaptureWriter, DEFAULT_MAX_PAYLOAD_LEN, Direction, FrameSplitter, Record,
+};
+use std::fs::File;
+use std::io::BufWriter;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
+
+type Capture = Arc<Mutex<CaptureWriter<BufWriter<File>>>>;
+
+fn parse_args() -> anyhow::Result<(String, u32, Vec<String>)> {
+    let mut capture = None;
+    let mut max_payload_len = DEFAULT_MAX_PAYLOAD_LEN;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--capture" => match args.next() {
+                Some(value) => capture = Some(value),
+                None => anyhow::bail!("no value for the --capture argument"),
+            },
+            "--max-payload-len" => match args.next() {
+                Some(value) => max_payload_len = value.parse()?,
+                None => anyhow::bail!("no value for the --max-payload-len argument"),
+            },
+            "--" => break,
+            _ => anyhow::bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    let Some(capture) = capture else {
+        anyhow::bail!("no capture file, usage: goridge-tap --capture FILE -- COMMAND [ARGS...]");
+    };
+
+    let cmd: Vec<String> = args.collect();
+    if cmd.is_empty() {
+        anyhow::bail!("no worker command, usage: goridge-tap --capture FILE -- COMMAND [ARGS...]");
+    }
+
+    Ok((capture, max_payload_len, cmd))
+}
+
+fn record(capture: &Capture, direction: Direction, chunks: Vec<Vec<u8>>) -> anyhow::Result<()> {
+    let mut capture = capture.lock().unwrap();
+    for data in chunks {
+        capture.write_record(&Record::new(direction, data))?;
+    }
+    // the worker could be killed at any moment, nothing should stay in the buffer
+    capture.flush()
+}
+
+// forwards the bytes as soon as they are read, frames are recorded once complete
+async fn forward<R, W>(
+    mut from: R,
+    mut to: W,
+    direction: Direction,
+    capture: Capture,
+    max_payload_len: u32,
+) -> anyhow::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut splitter = FrameSplitter::default();
+    splitter.set_max_payload_len(max_payload_len);
+    let mut chunk = vec![0; 64 * 1024];
+
+    loop {
+        let n = from.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+
+        let written = async {
+            to.write_all(&chunk[..n]).await?;
+            to.flush().await
+        }
+        .await;
+
+        record(&capture, direction, splitter.push(&chunk[..n]))?;
+        // the other side is gone, still keep the data it didn't receive in the capture
+        written?;
+    }
+
+    record(&capture, direction, splitter.finish().into_iter().collect())?;
+    // closes the pipe, the other side sees EOF
+    to.shutdown().await?;
+
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let (path, max_payload_len, cmd) = parse_args()?;
+
+    let capture = Arc::new(Mutex::new(CaptureWriter::new(BufWriter::new(
+        File::create(&path)?,
+    ))?));
+
+    let mut child = Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    let child_stdin = child.stdin.take().unwrap();
+    let child_stdout = child.stdout.take().unwrap();
+
+    // STDIN forwarding is not awaited, the parent might keep the pipe open after the worker exits
+    let to_worker = tokio::spawn(forward(
+        tokio::io::stdin(),
+        child_stdin,
+        Direction::ToWorker,
+        capture.clone(),
+        max_payload_len,
+    ));
+
+    let from_worker = forward(
+        child_stdout,
+        tokio::io::stdout(),
+        Direction::FromWorker,
+        capture.clone(),
+        max_payload_len,
+    )
+    .await;
+
+    let status = child.wait().await?;
+    to_worker.abort();
+    from_worker?;
+
+    capture.lock().unwrap().flush()?;
+
+    // killed by a signal has no exit code
+    std::process::exit(status.code().unwrap_or(1));
+}
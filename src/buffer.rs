@@ -0,0 +1,230 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// smallest size class, fits the 12 bytes header with the options
+pub const MIN_CLASS_SIZE: usize = 64;
+// biggest size class, 4MB, larger buffers are not pooled
+pub const MAX_CLASS_SIZE: usize = 4 * 1024 * 1024;
+pub const DEFAULT_BUFFERS_PER_CLASS: usize = 128;
+
+/// Size-classed (powers of two) pool of byte buffers.
+/// Every class holds at most `per_class` buffers, the rest are dropped on return.
+pub struct BufferPool {
+    classes: Vec<Mutex<Vec<Vec<u8>>>>,
+    per_class: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    returned: AtomicU64,
+    discarded: AtomicU64,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct PoolStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub returned: u64,
+    pub discarded: u64,
+}
+
+impl PoolStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+
+        self.hits as f64 / total as f64
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        BufferPool::new(DEFAULT_BUFFERS_PER_CLASS)
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("per_class", &self.per_class)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl BufferPool {
+    pub fn new(per_class: usize) -> Self {
+        let num_classes = (MAX_CLASS_SIZE / MIN_CLASS_SIZE).trailing_zeros() as usize + 1;
+
+        BufferPool {
+            classes: (0..num_classes).map(|_| Mutex::new(vec![])).collect(),
+            per_class,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            returned: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+        }
+    }
+
+    // class which is able to hold `size` bytes
+    #[inline]
+    fn class_for_size(size: usize) -> Option<usize> {
+        if size > MAX_CLASS_SIZE {
+            return None;
+        }
+
+        let size = size.max(MIN_CLASS_SIZE).next_power_of_two();
+        Some((size / MIN_CLASS_SIZE).trailing_zeros() as usize)
+    }
+
+    // largest class which the buffer with `capacity` could serve
+    #[inline]
+    fn class_for_capacity(capacity: usize) -> Option<usize> {
+        if !(MIN_CLASS_SIZE..=MAX_CLASS_SIZE).contains(&capacity) {
+            return None;
+        }
+
+        // round down to the power of two
+        let size = 1 << (usize::BITS - 1 - capacity.leading_zeros());
+        Some((size / MIN_CLASS_SIZE).trailing_zeros() as usize)
+    }
+
+    /// Returns zeroed buffer with the length of `size`.
+    pub fn get(&self, size: usize) -> Vec<u8> {
+        let Some(class) = Self::class_for_size(size) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return vec![0; size];
+        };
+
+        let pooled = self.classes[class].lock().unwrap().pop();
+
+        match pooled {
+            Some(mut buf) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                buf.clear();
+                buf.resize(size, 0);
+                buf
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                let mut buf = Vec::with_capacity(MIN_CLASS_SIZE << class);
+                buf.resize(size, 0);
+                buf
+            }
+        }
+    }
+
+    /// Returns buffer back to the pool, buffer is dropped if its class is full
+    /// or it is bigger than the biggest class.
+    pub fn put(&self, buf: Vec<u8>) {
+        let Some(class) = Self::class_for_capacity(buf.capacity()) else {
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+
+        let mut bufs = self.classes[class].lock().unwrap();
+        if bufs.len() >= self.per_class {
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        bufs.push(buf);
+        self.returned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            returned: self.returned.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::{BufferPool, MAX_CLASS_SIZE, MIN_CLASS_SIZE};
+    use crate::frame::Frame;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_reuse() {
+        let pool = BufferPool::new(2);
+
+        let mut buf = pool.get(100);
+        assert_eq!(buf.len(), 100);
+        buf[0] = 42;
+        pool.put(buf);
+
+        let buf = pool.get(120);
+        assert_eq!(buf.len(), 120);
+        assert!(buf.iter().all(|&b| b == 0));
+
+        let stats = pool.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.returned, 1);
+        assert_eq!(stats.hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let pool = BufferPool::new(1);
+
+        pool.put(vec![0; MIN_CLASS_SIZE]);
+        pool.put(vec![0; MIN_CLASS_SIZE]);
+        // too small to be pooled
+        pool.put(vec![0; 1]);
+
+        let stats = pool.stats();
+        assert_eq!(stats.returned, 1);
+        assert_eq!(stats.discarded, 2);
+    }
+
+    #[test]
+    fn test_oversize() {
+        let pool = BufferPool::new(1);
+
+        let buf = pool.get(MAX_CLASS_SIZE + 1);
+        assert_eq!(buf.len(), MAX_CLASS_SIZE + 1);
+        pool.put(buf);
+
+        // not pooled, the biggest class stays empty
+        let buf = pool.get(MAX_CLASS_SIZE);
+        assert_eq!(buf.len(), MAX_CLASS_SIZE);
+        pool.put(buf);
+
+        let stats = pool.stats();
+        assert_eq!(stats.hits, 0);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.returned, 1);
+        assert_eq!(stats.discarded, 1);
+    }
+
+    #[test]
+    fn test_frame_returns_buffers() {
+        let pool = Arc::new(BufferPool::default());
+
+        {
+            let mut fr = Frame::with_pool(pool.clone());
+            fr.init_payload_mut(1024);
+        }
+
+        assert_eq!(pool.stats().returned, 2);
+        // the empty payload replaced by the pooled one is not a buffer
+        assert_eq!(pool.stats().discarded, 0);
+
+        let mut fr = Frame::with_pool(pool.clone());
+        fr.init_payload_mut(1000);
+        assert_eq!(fr.header().len(), 12);
+        assert_eq!(fr.payload().len(), 1000);
+        assert_eq!(pool.stats().hits, 2);
+
+        // the frame without a payload
+        drop(fr);
+        drop(Frame::with_pool(pool.clone()));
+        assert_eq!(pool.stats().discarded, 0);
+    }
+}
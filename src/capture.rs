@@ -0,0 +1,311 @@
+use crate::frame::Frame;
+use crate::frame::header::{FrameHeader, HEADER_LEN};
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// capture file starts with the magic, followed by the records:
+// [direction: u8][timestamp, microseconds since UNIX epoch: u64 LE][len: u32 LE][data]
+pub const MAGIC: &[u8; 8] = b"GRCAP001";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Direction {
+    // parent STDIN -> worker
+    ToWorker = 0,
+    // worker STDOUT -> parent
+    FromWorker = 1,
+}
+
+/// Bytes which crossed the pipe: a whole frame or the garbage between the frames.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Record {
+    pub direction: Direction,
+    pub timestamp: SystemTime,
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    pub fn new(direction: Direction, data: Vec<u8>) -> Self {
+        Record {
+            direction,
+            timestamp: SystemTime::now(),
+            data,
+        }
+    }
+
+    /// Frame of the record, `None` for the garbage.
+    pub fn frame(&self) -> Option<Frame> {
+        match Frame::decode(&self.data) {
+            Ok(Some((frame, n))) if n == self.data.len() && frame.verify_crc().is_ok() => {
+                Some(frame)
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    pub fn new(mut writer: W) -> anyhow::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(CaptureWriter { writer })
+    }
+
+    pub fn write_record(&mut self, record: &Record) -> anyhow::Result<()> {
+        let micros = record
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        self.writer.write_all(&[record.direction as u8])?;
+        self.writer.write_all(&micros.to_le_bytes())?;
+        self.writer
+            .write_all(&(record.data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&record.data)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub struct CaptureReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            anyhow::bail!("not a capture file, magic is {:?}", magic);
+        }
+
+        Ok(CaptureReader { reader })
+    }
+
+    pub fn read_record(&mut self) -> anyhow::Result<Option<Record>> {
+        let mut direction = [0; 1];
+        if self.reader.read(&mut direction)? == 0 {
+            return Ok(None);
+        }
+
+        let direction = match direction[0] {
+            0 => Direction::ToWorker,
+            1 => Direction::FromWorker,
+            d => anyhow::bail!("unknown record direction: {}", d),
+        };
+
+        let mut micros = [0; 8];
+        self.reader.read_exact(&mut micros)?;
+        let mut len = [0; 4];
+        self.reader.read_exact(&mut len)?;
+
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(Record {
+            direction,
+            timestamp: UNIX_EPOCH + Duration::from_micros(u64::from_le_bytes(micros)),
+            data,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+// frames with the bigger payload are recorded as the garbage instead of being buffered
+pub const DEFAULT_MAX_PAYLOAD_LEN: u32 = 64 * 1024 * 1024;
+// the longer garbage is recorded in parts, so the noise-only stream isn't buffered forever
+const MAX_GARBAGE_LEN: usize = 64 * 1024;
+
+/// Cuts a byte stream at the frame boundaries, the bytes between the frames are returned as is.
+pub struct FrameSplitter {
+    buf: Vec<u8>,
+    // garbage seen so far, returned as a single chunk once the next frame starts
+    garbage: Vec<u8>,
+    max_payload_len: u32,
+}
+
+impl Default for FrameSplitter {
+    fn default() -> Self {
+        FrameSplitter {
+            buf: vec![],
+            garbage: vec![],
+            max_payload_len: DEFAULT_MAX_PAYLOAD_LEN,
+        }
+    }
+}
+
+impl FrameSplitter {
+    /// Same limit as `FrameReader::set_max_payload_len`, the bigger frames are not waited for.
+    pub fn set_max_payload_len(&mut self, len: u32) {
+        self.max_payload_len = len;
+    }
+
+    #[inline]
+    fn valid_header(&self, data: &[u8]) -> bool {
+        let header: &[u8; HEADER_LEN] = data[..HEADER_LEN].try_into().unwrap();
+        FrameHeader::parse(header)
+            .is_ok_and(|h| h.verify_crc().is_ok() && h.payload_len <= self.max_payload_len)
+    }
+
+    /// Returns the complete chunks, incomplete frame is kept until more data is pushed.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        self.buf.extend_from_slice(data);
+
+        let mut chunks = vec![];
+        while self.buf.len() >= HEADER_LEN {
+            if self.valid_header(&self.buf) {
+                // the garbage ends at the header
+                if !self.garbage.is_empty() {
+                    chunks.push(std::mem::take(&mut self.garbage));
+                }
+
+                match Frame::decode(&self.buf) {
+                    Ok(Some((_, n))) => {
+                        chunks.push(self.buf.drain(..n).collect());
+                        continue;
+                    }
+                    // wait for the rest of the frame
+                    Ok(None) => break,
+                    Err(_) => {}
+                }
+            }
+
+            // garbage up to the next valid header, the tail could be the beginning of the header
+            let end = (1..=self.buf.len() - HEADER_LEN)
+                .find(|&i| self.valid_header(&self.buf[i..]))
+                .unwrap_or(self.buf.len() - (HEADER_LEN - 1));
+
+            self.garbage.extend(self.buf.drain(..end));
+            if self.garbage.len() >= MAX_GARBAGE_LEN {
+                chunks.push(std::mem::take(&mut self.garbage));
+            }
+        }
+
+        chunks
+    }
+
+    /// Returns the rest of the data at the end of the stream.
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        let mut rest = std::mem::take(&mut self.garbage);
+        rest.append(&mut self.buf);
+
+        match rest.is_empty() {
+            true => None,
+            false => Some(rest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capture::{CaptureReader, CaptureWriter, Direction, FrameSplitter, Record};
+    use crate::frame::Frame;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_payload(payload);
+        ff.write_crc();
+        ff.bytes()
+    }
+
+    #[test]
+    fn test_capture_round_trip() {
+        let records = vec![
+            Record::new(Direction::ToWorker, frame(b"hello")),
+            Record::new(Direction::FromWorker, b"warning".to_vec()),
+        ];
+
+        let mut buf = vec![];
+        let mut writer = CaptureWriter::new(&mut buf).unwrap();
+        for record in &records {
+            writer.write_record(record).unwrap();
+        }
+
+        let reader = CaptureReader::new(buf.as_slice()).unwrap();
+        let res: Vec<Record> = reader.map(|r| r.unwrap()).collect();
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].direction, Direction::ToWorker);
+        assert_eq!(res[0].frame().unwrap().payload(), b"hello");
+        assert_eq!(res[1].data, b"warning");
+        assert!(res[1].frame().is_none());
+        // microseconds precision
+        let diff = records[0]
+            .timestamp
+            .duration_since(res[0].timestamp)
+            .unwrap();
+        assert!(diff.as_micros() < 1);
+    }
+
+    #[test]
+    fn test_capture_bad_magic() {
+        assert!(CaptureReader::new(b"GRCAP999".as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_splitter() {
+        let mut data = frame(b"hello");
+        data.extend(b"warning: some weird php error");
+        data.extend(frame(b"world"));
+
+        let mut splitter = FrameSplitter::default();
+        let mut chunks = vec![];
+        // byte by byte, like the smallest reads from the pipe
+        for b in &data {
+            chunks.extend(splitter.push(&[*b]));
+        }
+        chunks.extend(splitter.finish());
+
+        // the garbage is a single chunk however small the reads are
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0], frame(b"hello"));
+        assert_eq!(chunks[1], b"warning: some weird php error");
+        assert_eq!(chunks[2], frame(b"world"));
+    }
+
+    #[test]
+    fn test_splitter_payload_limit() {
+        // valid header of the frame which never ends
+        let mut huge = Frame::default();
+        huge.write_version(1);
+        huge.write_payload(b"hello");
+        huge.header_mut()[5] = 0x40;
+        huge.write_crc();
+
+        let mut data = huge.bytes();
+        data.extend(frame(b"world"));
+
+        let mut splitter = FrameSplitter::default();
+        splitter.set_max_payload_len(1024);
+        let chunks = splitter.push(&data);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], huge.bytes());
+        assert_eq!(chunks[1], frame(b"world"));
+        assert!(splitter.finish().is_none());
+    }
+
+    #[test]
+    fn test_splitter_finish() {
+        let mut splitter = FrameSplitter::default();
+        assert!(splitter.push(b"warning: some weird php error").is_empty());
+
+        assert_eq!(splitter.finish().unwrap(), b"warning: some weird php error");
+        assert!(splitter.finish().is_none());
+    }
+}
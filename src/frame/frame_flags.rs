@@ -1,4 +1,7 @@
-#[derive(Debug, Copy, Clone)]
+use bitflags::bitflags;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Flag {
     Control = 0x01,
     CodecRaw = 0x04,
@@ -8,3 +11,108 @@ pub enum Flag {
     Error = 0x40,
     CodecProto = 0x80,
 }
+
+bitflags! {
+    /// Set of the frame flags (header byte 1).
+    /// Unknown bits are preserved as is for forward compatibility.
+    #[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+    pub struct Flags: u8 {
+        const CONTROL = Flag::Control as u8;
+        const CODEC_RAW = Flag::CodecRaw as u8;
+        const CODEC_JSON = Flag::CodecJSON as u8;
+        const CODEC_MSGPACK = Flag::CodecMsgpack as u8;
+        const CODEC_GOB = Flag::CodecGob as u8;
+        const ERROR = Flag::Error as u8;
+        const CODEC_PROTO = Flag::CodecProto as u8;
+
+        // bits which are not known yet
+        const _ = !0;
+    }
+}
+
+impl Flags {
+    pub const CODECS: Flags = Flags::CODEC_RAW
+        .union(Flags::CODEC_JSON)
+        .union(Flags::CODEC_MSGPACK)
+        .union(Flags::CODEC_GOB)
+        .union(Flags::CODEC_PROTO);
+
+    /// Codec flag of the frame, `None` if no codec is set.
+    pub fn codec(&self) -> anyhow::Result<Option<Flag>> {
+        let codecs = self.intersection(Flags::CODECS);
+
+        match codecs {
+            c if c.is_empty() => Ok(None),
+            Flags::CODEC_RAW => Ok(Some(Flag::CodecRaw)),
+            Flags::CODEC_JSON => Ok(Some(Flag::CodecJSON)),
+            Flags::CODEC_MSGPACK => Ok(Some(Flag::CodecMsgpack)),
+            Flags::CODEC_GOB => Ok(Some(Flag::CodecGob)),
+            Flags::CODEC_PROTO => Ok(Some(Flag::CodecProto)),
+            c => anyhow::bail!("only one codec flag should be set, got {:?}", c),
+        }
+    }
+}
+
+impl From<Flag> for Flags {
+    fn from(flag: Flag) -> Self {
+        Flags::from_bits_retain(flag as u8)
+    }
+}
+
+impl From<&[Flag]> for Flags {
+    fn from(flags: &[Flag]) -> Self {
+        flags
+            .iter()
+            .fold(Flags::empty(), |acc, &flag| acc | flag.into())
+    }
+}
+
+impl fmt::Debug for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "Flags(empty)");
+        }
+
+        write!(f, "Flags(")?;
+        bitflags::parser::to_writer(self, &mut *f)?;
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::frame_flags::{Flag, Flags};
+
+    #[test]
+    fn test_flags_set() {
+        let mut flags = Flags::from([Flag::Control, Flag::CodecJSON].as_slice());
+        assert!(flags.contains(Flags::CONTROL));
+        assert!(!flags.contains(Flags::ERROR));
+
+        flags.insert(Flags::ERROR);
+        flags.remove(Flags::CONTROL);
+        assert_eq!(flags, Flags::ERROR | Flags::CODEC_JSON);
+        assert_eq!(format!("{:?}", flags), "Flags(CODEC_JSON | ERROR)");
+        assert_eq!(format!("{:?}", Flags::empty()), "Flags(empty)");
+    }
+
+    #[test]
+    fn test_flags_codec() {
+        assert_eq!(Flags::CONTROL.codec().unwrap(), None);
+        assert_eq!(
+            (Flags::CONTROL | Flags::CODEC_PROTO).codec().unwrap(),
+            Some(Flag::CodecProto)
+        );
+        assert!((Flags::CODEC_RAW | Flags::CODEC_JSON).codec().is_err());
+    }
+
+    #[test]
+    fn test_flags_unknown_bits() {
+        let flags = Flags::from_bits_retain(0x02 | Flag::Control as u8);
+        assert_eq!(flags.bits(), 0x03);
+        assert_eq!(format!("{:?}", flags), "Flags(CONTROL | 0x2)");
+
+        let flags = flags.difference(Flags::CONTROL);
+        assert_eq!(flags.bits(), 0x02);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::frame::frame_flags::Flags;
+use crate::frame::{MAX_OPTIONS, WORD};
+
+pub const HEADER_LEN: usize = 12;
+// header len in 32-bit words without options
+pub const MIN_HL: u8 = 3;
+pub const MAX_HL: u8 = MIN_HL + MAX_OPTIONS;
+
+/// Fixed 12 bytes part of the frame header.
+/// Options words (if any) follow it on the wire and are not a part of this type.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct FrameHeader {
+    pub version: u8,
+    // header length in 32-bit words, including options
+    pub hl: u8,
+    pub flags: Flags,
+    pub payload_len: u32,
+    pub crc: u32,
+}
+
+impl Default for FrameHeader {
+    fn default() -> Self {
+        FrameHeader {
+            version: 0,
+            hl: MIN_HL,
+            flags: Flags::empty(),
+            payload_len: 0,
+            crc: 0,
+        }
+    }
+}
+
+impl FrameHeader {
+    pub fn parse(data: &[u8; HEADER_LEN]) -> anyhow::Result<Self> {
+        let header = FrameHeader {
+            version: data[0] >> 4,
+            hl: data[0] & 0x0F,
+            flags: Flags::from_bits_retain(data[1]),
+            payload_len: u32::from_le_bytes([data[2], data[3], data[4], data[5]]),
+            crc: u32::from_le_bytes([data[6], data[7], data[8], data[9]]),
+        };
+
+        header.validate()?;
+        Ok(header)
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut data = [0; HEADER_LEN];
+        data[0] = (self.version << 4) | (self.hl & 0x0F);
+        data[1] = self.flags.bits();
+        data[2..6].copy_from_slice(&self.payload_len.to_le_bytes());
+        data[6..10].copy_from_slice(&self.crc.to_le_bytes());
+        data
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.version > 15 {
+            anyhow::bail!(
+                "version should be less than 2 bytes (15), got {}",
+                self.version
+            );
+        }
+
+        if !(MIN_HL..=MAX_HL).contains(&self.hl) {
+            anyhow::bail!(
+                "header len should be in range [{}..{}], got {}",
+                MIN_HL,
+                MAX_HL,
+                self.hl
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Size of the options words following the fixed header, in bytes.
+    #[inline]
+    pub fn options_len(&self) -> usize {
+        (self.hl.saturating_sub(MIN_HL) * WORD) as usize
+    }
+
+    /// CRC32 of the first 6 header bytes, same as `Frame::write_crc`.
+    pub fn compute_crc(&self) -> u32 {
+        crc32fast::hash(&self.to_bytes()[..6])
+    }
+
+    pub fn write_crc(&mut self) {
+        self.crc = self.compute_crc();
+    }
+
+    pub fn verify_crc(&self) -> anyhow::Result<()> {
+        let crc = self.compute_crc();
+        match crc == self.crc {
+            true => Ok(()),
+            false => anyhow::bail!(
+                "CRC verification failed: expected {}, got {}",
+                crc,
+                self.crc
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::Frame;
+    use crate::frame::frame_flags::{Flag, Flags};
+    use crate::frame::header::{FrameHeader, HEADER_LEN};
+
+    #[test]
+    fn test_parse_frame_header() {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_flags(&[Flag::Control, Flag::CodecJSON]);
+        ff.write_payload(b"hello");
+        ff.write_options(&[1, 2]).unwrap();
+        ff.write_crc();
+
+        let bytes: [u8; HEADER_LEN] = ff.header()[..HEADER_LEN].try_into().unwrap();
+        let header = FrameHeader::parse(&bytes).unwrap();
+
+        assert_eq!(header.version, 1);
+        assert_eq!(header.hl, 5);
+        assert_eq!(header.flags, Flags::CONTROL | Flags::CODEC_JSON);
+        assert_eq!(header.payload_len, 5);
+        assert_eq!(header.options_len(), 8);
+        assert!(header.verify_crc().is_ok());
+        assert_eq!(header.to_bytes(), bytes);
+        assert_eq!(ff.read_header().unwrap(), header);
+    }
+
+    #[test]
+    fn test_rewrite_frame_header() {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_payload(b"hello");
+        ff.write_crc();
+
+        let mut header = ff.read_header().unwrap();
+        header.flags = Flags::ERROR;
+        header.write_crc();
+        ff.write_header(&header).unwrap();
+
+        assert_eq!(ff.read_flags(), Flags::ERROR);
+        assert!(ff.verify_crc().is_ok());
+        assert_eq!(ff.payload(), b"hello");
+
+        // options can't be added via the header
+        header.hl += 1;
+        assert!(ff.write_header(&header).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_keeps_reserved_bytes() {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_payload(b"hello");
+        ff.write_crc();
+
+        let mut data = ff.bytes();
+        data[10] = 0xAB;
+        data[11] = 0xCD;
+        let mut ff = ff.read_frame(&data).unwrap();
+
+        let mut header = ff.read_header().unwrap();
+        header.flags = Flags::ERROR;
+        header.write_crc();
+        ff.write_header(&header).unwrap();
+
+        assert_eq!(ff.header()[10..HEADER_LEN], [0xAB, 0xCD]);
+        assert!(ff.verify_crc().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_frame_header() {
+        let mut bytes = [0; HEADER_LEN];
+        assert!(FrameHeader::parse(&bytes).is_err());
+
+        bytes[0] = 0x1F;
+        assert!(FrameHeader::parse(&bytes).is_err());
+
+        bytes[0] = 0x13;
+        assert!(FrameHeader::parse(&bytes).unwrap().verify_crc().is_err());
+    }
+}
@@ -0,0 +1,93 @@
+/// Integrity checks applied by a relay to every frame it sends and receives.
+/// Both sides of the relay should use the same mode.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Integrity {
+    // no checks at all, for the trusted local pipes
+    None,
+    // CRC32 of the first 6 header bytes, wire compatible with the goridge protocol
+    #[default]
+    Header,
+    // header CRC plus CRC32C of the payload, carried in the last option word
+    HeaderPayload,
+}
+
+impl Integrity {
+    #[inline]
+    pub fn verify_header(&self) -> bool {
+        *self != Integrity::None
+    }
+
+    #[inline]
+    pub fn verify_payload(&self) -> bool {
+        *self == Integrity::HeaderPayload
+    }
+}
+
+/// CRC32C (Castagnoli) of the payload, uses SSE4.2/ARMv8 CRC instructions when available.
+#[inline]
+pub fn payload_crc(payload: &[u8]) -> u32 {
+    crc32c::crc32c(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::Frame;
+    use crate::frame::integrity::{Integrity, payload_crc};
+
+    #[test]
+    fn test_payload_crc() {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_options(&[42]).unwrap();
+        ff.write_payload(b"hello");
+        ff.write_payload_crc().unwrap();
+        ff.write_crc();
+
+        let bytes = ff.bytes();
+        let mut res = Frame::default().read_frame(&bytes).unwrap();
+        assert!(res.verify_crc().is_ok());
+        assert!(res.verify_payload_crc().is_ok());
+        assert_eq!(res.read_options().unwrap(), vec![42, payload_crc(b"hello")]);
+
+        res.strip_payload_crc().unwrap();
+        assert!(res.verify_crc().is_ok());
+        assert_eq!(res.read_options().unwrap(), vec![42]);
+    }
+
+    #[test]
+    fn test_payload_crc_corrupted() {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_payload(b"hello");
+        ff.write_payload_crc().unwrap();
+        ff.write_crc();
+
+        let mut bytes = ff.bytes();
+        let last = bytes.len() - 1;
+        bytes[last] = b'O';
+
+        let res = Frame::default().read_frame(&bytes).unwrap();
+        // header is still fine
+        assert!(res.verify_crc().is_ok());
+        assert!(res.verify_payload_crc().is_err());
+    }
+
+    #[test]
+    fn test_payload_crc_missing() {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_payload(b"hello");
+        ff.write_crc();
+
+        assert!(ff.verify_payload_crc().is_err());
+        assert!(ff.strip_payload_crc().is_err());
+    }
+
+    #[test]
+    fn test_integrity_modes() {
+        assert!(!Integrity::None.verify_header());
+        assert!(Integrity::default().verify_header());
+        assert!(!Integrity::default().verify_payload());
+        assert!(Integrity::HeaderPayload.verify_payload());
+    }
+}
@@ -1,17 +1,28 @@
+use crate::buffer::BufferPool;
+use crate::frame::header::{FrameHeader, HEADER_LEN};
+use crate::frame::version::VERSION_1;
+use std::cmp::Ordering;
 use std::ops::Shl;
+use std::sync::Arc;
 use std::vec;
 
 pub mod frame_flags;
+pub mod header;
+pub mod integrity;
+pub mod version;
 
 pub const WORD: u8 = 4;
 pub const FRAME_OPTIONS_MAX_SIZE: u8 = 40;
+pub const MAX_OPTIONS: u8 = FRAME_OPTIONS_MAX_SIZE / WORD;
 const LAST_BYTE: u8 = 12;
 
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct Frame {
     // 52 is maximum header len [0-51] or [0-52)
     header: Vec<u8>,
     payload: Vec<u8>,
+    // header and payload buffers are returned to the pool on drop
+    pool: Option<Arc<BufferPool>>,
 }
 
 impl Default for Frame {
@@ -19,16 +30,70 @@ impl Default for Frame {
         let mut f = Frame {
             header: vec![0; 12],
             payload: vec![],
+            pool: None,
         };
         f.default_hl();
+        f.write_version(VERSION_1);
         f
     }
 }
 
+impl Drop for Frame {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            put_back(&pool, std::mem::take(&mut self.header));
+            put_back(&pool, std::mem::take(&mut self.payload));
+        }
+    }
+}
+
+// the empty payload was never allocated, it isn't counted as discarded
+#[inline]
+fn put_back(pool: &BufferPool, buf: Vec<u8>) {
+    if buf.capacity() > 0 {
+        pool.put(buf);
+    }
+}
+
+impl PartialEq for Frame {
+    fn eq(&self, other: &Self) -> bool {
+        self.header == other.header && self.payload == other.payload
+    }
+}
+
+impl Eq for Frame {}
+
+impl PartialOrd for Frame {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frame {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.header
+            .cmp(&other.header)
+            .then_with(|| self.payload.cmp(&other.payload))
+    }
+}
+
 impl Frame {
+    /// Frame which draws its header and payload buffers from the pool.
+    pub fn with_pool(pool: Arc<BufferPool>) -> Self {
+        let mut f = Frame {
+            header: pool.get(12),
+            payload: vec![],
+            pool: Some(pool),
+        };
+        f.default_hl();
+        f.write_version(VERSION_1);
+        f
+    }
+
     #[inline]
     fn write_hl(&mut self, hl: u8) {
-        self.header[0] |= hl;
+        // keep the version nibble untouched
+        self.header[0] = (self.header[0] & 0xF0) | (hl & 0x0F);
     }
 
     #[inline]
@@ -47,7 +112,14 @@ impl Frame {
     }
 
     pub fn init_payload_mut(&mut self, size: usize) -> &mut Vec<u8> {
-        self.payload = vec![0; size];
+        match &self.pool {
+            Some(pool) => {
+                let old = std::mem::replace(&mut self.payload, pool.get(size));
+                put_back(pool, old);
+            }
+            None => self.payload = vec![0; size],
+        }
+
         &mut self.payload
     }
 
@@ -55,29 +127,96 @@ impl Frame {
         self.header.extend(data.iter());
     }
 
-    pub fn read_frame(&self, data: &[u8]) -> Self {
+    pub fn read_frame(&self, data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < HEADER_LEN {
+            anyhow::bail!("frame is too short: {} bytes", data.len());
+        }
+
         // get options bits
         let opt = data[0] & 0x0F;
 
         match opt {
-            // 3 is minimum
-            1..=3 => {
-                let mut frame = Frame {
-                    header: data[..12].to_vec(),
-                    payload: data[12..].to_vec(),
-                };
+            // 3 is minimum, the same as `Frame::decode` accepts
+            0..=2 => anyhow::bail!("header len should be at least 3 words, got {}", opt),
+            3 => Ok(Frame {
+                header: data[..HEADER_LEN].to_vec(),
+                payload: data[HEADER_LEN..].to_vec(),
+                pool: None,
+            }),
+
+            _ => {
+                if (opt - 3) * WORD > FRAME_OPTIONS_MAX_SIZE {
+                    anyhow::bail!("options size is limited by 40 bytes (10 4-bytes words)");
+                }
+
+                let hl = (opt * WORD) as usize;
+                if data.len() < hl {
+                    anyhow::bail!(
+                        "frame is too short: header len is {} bytes, got {} bytes",
+                        hl,
+                        data.len()
+                    );
+                }
+
+                Ok(Self {
+                    header: data[..hl].to_vec(),
+                    payload: data[hl..].to_vec(),
+                    pool: None,
+                })
+            }
+        }
+    }
 
-                frame.header[10] = 0;
-                frame.header[11] = 0;
+    /// Decodes the frame from the beginning of a byte stream, `None` if more bytes are needed.
+    /// Unlike `read_frame`, the payload is bounded by the payload len from the header.
+    /// Returns the frame and the number of bytes it occupies, CRC is not verified.
+    pub fn decode(data: &[u8]) -> anyhow::Result<Option<(Frame, usize)>> {
+        let Some(header) = data.get(..HEADER_LEN) else {
+            return Ok(None);
+        };
 
-                frame
-            }
+        let header = FrameHeader::parse(header.try_into()?)?;
+        let hl = header.hl as usize * WORD as usize;
+        let total = hl + header.payload_len as usize;
 
-            _ => Self {
-                header: data[..(opt * WORD) as usize].to_vec(),
-                payload: data[(opt * WORD) as usize..].to_vec(),
-            },
+        if data.len() < total {
+            return Ok(None);
         }
+
+        let frame = Frame {
+            header: data[..hl].to_vec(),
+            payload: data[hl..total].to_vec(),
+            pool: None,
+        };
+
+        Ok(Some((frame, total)))
+    }
+
+    /// Copy of the fixed 12 bytes header.
+    pub fn read_header(&self) -> anyhow::Result<FrameHeader> {
+        let data: &[u8; HEADER_LEN] = match self.header.get(..HEADER_LEN) {
+            Some(data) => data.try_into()?,
+            None => anyhow::bail!("header is too short: {} bytes", self.header.len()),
+        };
+
+        FrameHeader::parse(data)
+    }
+
+    /// Rewrites the fixed 12 bytes header, options words and payload are kept as is.
+    pub fn write_header(&mut self, header: &FrameHeader) -> anyhow::Result<()> {
+        header.validate()?;
+
+        if header.hl != self.read_hl() {
+            anyhow::bail!(
+                "header len mismatch: frame has {}, got {}",
+                self.read_hl(),
+                header.hl
+            );
+        }
+
+        // the reserved bytes 10 and 11 are kept as is
+        self.header[..10].copy_from_slice(&header.to_bytes()[..10]);
+        Ok(())
     }
 
     #[inline]
@@ -91,7 +230,7 @@ impl Frame {
             panic!("version should be less than 2 bytes (15)")
         }
 
-        self.header[0] |= version << 4
+        self.header[0] = (self.header[0] & 0x0F) | (version << 4)
     }
 
     #[inline]
@@ -106,12 +245,18 @@ impl Frame {
             panic!("header len can't be more than 15 (4bits)");
         }
 
-        self.header[0] = (self.header[0] | hl) + 1
+        self.write_hl(hl + 1)
     }
 
     #[inline]
-    pub fn read_flags(&self) -> u8 {
-        self.header[1]
+    pub fn read_flags(&self) -> frame_flags::Flags {
+        frame_flags::Flags::from_bits_retain(self.header[1])
+    }
+
+    /// Replaces all flags, unlike `write_flags` which could only add them.
+    #[inline]
+    pub fn set_flags(&mut self, flags: frame_flags::Flags) {
+        self.header[1] = flags.bits();
     }
 
     #[inline]
@@ -122,201 +267,170 @@ impl Frame {
     }
 
     pub fn write_payload(&mut self, payload: &[u8]) {
-        let pl = payload.len();
+        self.payload.extend_from_slice(payload);
+
+        // payload could be written in parts, header holds the whole length
+        let pl = self.payload.len();
         self.header[2] = pl as u8;
         self.header[3] = (pl >> 8) as u8;
         self.header[4] = (pl >> 16) as u8;
         self.header[5] = (pl >> 24) as u8;
-
-        self.payload.extend_from_slice(payload);
     }
 
-    pub fn write_options(&mut self, options: &[u32]) {
-        if options.is_empty() {
-            panic!("no options provided");
-        }
-
-        if options.len() > 10 {
-            panic!("header options limited by 40 bytes");
-        }
-
+    // number of the option words, 0 for the malformed header
+    #[inline]
+    fn options_count(&self) -> usize {
         let hl = self.read_hl();
-
-        if hl == 15 {
-            panic!("header len could not be more than 14 [0..15)");
+        if hl <= 3 {
+            return 0;
         }
 
-        for (i, &option) in options.iter().enumerate() {
-            let j = 12 + i * WORD as usize;
-
-            self.header.push(0);
-            self.header[j] |= option as u8;
-
-            self.header.push(0);
-            self.header[j + 1] |= (option >> 8) as u8;
+        let count = hl - 3;
+        // options size is limited by 40 bytes (10 4-bytes words)
+        if count > MAX_OPTIONS || self.header.len() < (hl * WORD) as usize {
+            return 0;
+        }
 
-            self.header.push(0);
-            self.header[j + 2] |= (option >> 16) as u8;
+        count as usize
+    }
 
-            self.header.push(0);
-            self.header[j + 3] |= (option >> 24) as u8;
+    pub fn options(&self) -> impl Iterator<Item = u32> + '_ {
+        let end = LAST_BYTE as usize + self.options_count() * WORD as usize;
 
-            self.increment_hl(); // increment header len by 32 bit
-        }
+        self.header[LAST_BYTE as usize..end]
+            .chunks_exact(WORD as usize)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
     }
 
-    pub fn read_options(&mut self) -> Option<Vec<u32>> {
-        let ol = self.read_hl(); // options lens
-
+    pub fn read_options(&self) -> Option<Vec<u32>> {
         // don't have any options
-        if ol <= 3 {
+        if self.options_count() == 0 {
             return None;
         }
 
-        // actual option len
-        let option_len = ol - 3;
-
-        if option_len * WORD > FRAME_OPTIONS_MAX_SIZE {
-            panic!("options size is limited by 40 bytes (10 4-bytes words)")
-        }
-
-        let mut options = vec![0; option_len as usize];
-
-        // 1
-        let mut i = 0;
-        let mut j = 0;
-
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
-
-        i += WORD;
-        j += 1;
+        Some(self.options().collect())
+    }
 
-        if i == option_len * WORD {
-            return Some(options);
+    /// Appends the option word, up to 10 words in total.
+    pub fn push_option(&mut self, option: u32) -> anyhow::Result<()> {
+        if self.read_hl() < 3 {
+            self.default_hl();
         }
 
-        // 2
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
-
-        i += WORD;
-        j += 1;
-
-        if i == option_len * WORD {
-            return Some(options);
+        let count = self.read_hl().saturating_sub(3);
+        if count >= MAX_OPTIONS {
+            anyhow::bail!("header options limited by 40 bytes (10 4-bytes words)");
         }
 
-        // 3
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
+        // options could be written more than once, append after the existing ones
+        let start = (3 + count) as usize * WORD as usize;
+        self.header.truncate(start);
+        self.header.extend_from_slice(&option.to_le_bytes());
+        self.increment_hl(); // increment header len by 32 bit
 
-        i += WORD;
-        j += 1;
+        Ok(())
+    }
 
-        if i == option_len * WORD {
-            return Some(options);
+    /// Replaces the already written option word.
+    pub fn set_option(&mut self, idx: usize, option: u32) -> anyhow::Result<()> {
+        let count = self.options_count();
+        if idx >= count {
+            anyhow::bail!(
+                "option index {} is out of range, frame has {} options",
+                idx,
+                count
+            );
         }
 
-        // 4
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
+        let start = LAST_BYTE as usize + idx * WORD as usize;
+        self.header[start..start + WORD as usize].copy_from_slice(&option.to_le_bytes());
 
-        i += WORD;
-        j += 1;
+        Ok(())
+    }
 
-        if i == option_len * WORD {
-            return Some(options);
+    pub fn write_options(&mut self, options: &[u32]) -> anyhow::Result<()> {
+        if options.is_empty() {
+            anyhow::bail!("no options provided");
         }
 
-        // 5
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
-
-        i += WORD;
-        j += 1;
-
-        if i == option_len * WORD {
-            return Some(options);
+        let count = self.read_hl().saturating_sub(3) as usize;
+        if count + options.len() > MAX_OPTIONS as usize {
+            anyhow::bail!(
+                "header options limited by 40 bytes (10 4-bytes words), frame has {} options, got {} more",
+                count,
+                options.len()
+            );
         }
 
-        // 6
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
-
-        i += WORD;
-        j += 1;
-
-        if i == option_len * WORD {
-            return Some(options);
+        for &option in options {
+            self.push_option(option)?;
         }
 
-        // 7
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
+        Ok(())
+    }
 
-        i += WORD;
-        j += 1;
+    pub fn write_crc(&mut self) {
+        let res = crc32fast::hash(&self.header[..6]);
+        self.header[6] = res as u8;
+        self.header[7] = (res >> 8) as u8;
+        self.header[8] = (res >> 16) as u8;
+        self.header[9] = (res >> 24) as u8;
+    }
 
-        if i == option_len * WORD {
-            return Some(options);
-        }
+    /// Appends CRC32C of the payload as the last option word.
+    /// Should be called after all other options are written and before `write_crc`.
+    pub fn write_payload_crc(&mut self) -> anyhow::Result<()> {
+        let crc = integrity::payload_crc(&self.payload);
+        self.push_option(crc)
+    }
 
-        // 8
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
+    /// Encoded frame with the payload CRC32C appended as the last option word,
+    /// the frame itself is left untouched so it could be sent again.
+    pub fn bytes_with_payload_crc(&self) -> anyhow::Result<Vec<u8>> {
+        let mut header = Frame {
+            header: self.header.clone(),
+            payload: vec![],
+            pool: None,
+        };
+        header.push_option(integrity::payload_crc(&self.payload))?;
+        header.write_crc();
 
-        i += WORD;
-        j += 1;
+        let mut v = Vec::with_capacity(header.header.len() + self.payload.len());
+        v.extend_from_slice(&header.header);
+        v.extend_from_slice(&self.payload);
+        Ok(v)
+    }
 
-        if i == option_len * WORD {
-            return Some(options);
+    // last option word, written by the `write_payload_crc`
+    fn read_payload_crc(&self) -> anyhow::Result<u32> {
+        match self.options().last() {
+            Some(crc) => Ok(crc),
+            None => anyhow::bail!("frame has no payload CRC option"),
         }
+    }
 
-        // 9
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
-
-        i += WORD;
-        j += 1;
-
-        if i == option_len * WORD {
-            return Some(options);
+    pub fn verify_payload_crc(&self) -> anyhow::Result<()> {
+        let expected = self.read_payload_crc()?;
+        let crc = integrity::payload_crc(&self.payload);
+        match crc == expected {
+            true => Ok(()),
+            false => anyhow::bail!(
+                "payload CRC verification failed: expected {}, got {}",
+                crc,
+                expected
+            ),
         }
-
-        // 10
-        options[j] |= self.header[(LAST_BYTE + i) as usize] as u32;
-        options[j] |= (self.header[(LAST_BYTE + i + 1) as usize] as u32) << 8;
-        options[j] |= (self.header[(LAST_BYTE + i + 2) as usize] as u32) << 16;
-        options[j] |= (self.header[(LAST_BYTE + i + 3) as usize] as u32) << 24;
-
-        Some(options)
     }
 
-    pub fn write_crc(&mut self) {
-        let res = crc32fast::hash(&self.header[..6]);
-        self.header[6] = res as u8;
-        self.header[7] = (res >> 8) as u8;
-        self.header[8] = (res >> 16) as u8;
-        self.header[9] = (res >> 24) as u8;
+    /// Removes the payload CRC option word and rewrites the header CRC.
+    pub fn strip_payload_crc(&mut self) -> anyhow::Result<()> {
+        self.read_payload_crc()?;
+
+        let hl = self.read_hl();
+        self.header.truncate((hl - 1) as usize * WORD as usize);
+        self.header[0] = (self.header[0] & 0xF0) | (hl - 1);
+        self.write_crc();
+        Ok(())
     }
 
     pub fn payload(&self) -> &Vec<u8> {
@@ -364,8 +478,10 @@ impl From<&mut Frame> for Vec<u8> {
     }
 }
 
-impl From<Vec<u8>> for Frame {
-    fn from(data: Vec<u8>) -> Self {
+impl TryFrom<Vec<u8>> for Frame {
+    type Error = anyhow::Error;
+
+    fn try_from(data: Vec<u8>) -> Result<Self, Self::Error> {
         Frame::default().read_frame(&data)
     }
 }
@@ -396,7 +512,7 @@ mod tests {
 
         let bytes = ff.bytes();
 
-        let res = Frame::default().read_frame(&bytes);
+        let res = Frame::default().read_frame(&bytes).unwrap();
         if let Err(err) = res.verify_crc() {
             panic!("should not be error: {}", err)
         }
@@ -416,7 +532,7 @@ mod tests {
 
         let bytes = ff.bytes();
 
-        let res = Frame::default().read_frame(&bytes);
+        let res = Frame::default().read_frame(&bytes).unwrap();
         if let Err(err) = res.verify_crc() {
             panic!("should not be error: {}", err)
         }
@@ -432,7 +548,7 @@ mod tests {
 
         let bytes = ff.bytes();
 
-        let res = Frame::default().read_frame(&bytes);
+        let res = Frame::default().read_frame(&bytes).unwrap();
         if let Ok(()) = res.verify_crc() {
             panic!("CRC verification was failed")
         }
@@ -446,11 +562,12 @@ mod tests {
         ff.write_version(1);
         ff.write_flags(&[Flag::Control, Flag::CodecRaw]);
         ff.write_payload(b"hello");
-        ff.write_options(&[1011, 1122, 1233, 1315, 1415, 1555, 1615, 1715, 1815]);
+        ff.write_options(&[1011, 1122, 1233, 1315, 1415, 1555, 1615, 1715, 1815])
+            .unwrap();
         ff.write_crc();
 
         let bytes = ff.bytes();
-        let mut res = Frame::default().read_frame(&bytes);
+        let res = Frame::default().read_frame(&bytes).unwrap();
 
         if res.verify_crc().is_err() {
             panic!("CRC verification was failed")
@@ -463,4 +580,191 @@ mod tests {
         assert_eq!(ff.version(), res.version());
         assert_eq!(ff.payload(), res.payload());
     }
+
+    #[test]
+    fn test_rewrite_version() {
+        let mut ff = Frame::default();
+        ff.write_version(15);
+        ff.write_version(1);
+        ff.write_options(&[1]).unwrap();
+
+        assert_eq!(ff.version(), 1);
+        assert_eq!(ff.read_hl(), 4);
+    }
+
+    #[test]
+    fn test_payload_in_parts() {
+        let mut ff = Frame::default();
+        ff.write_payload(b"hel");
+        ff.write_payload(b"lo");
+
+        assert_eq!(ff.read_payload_len(), 5);
+        assert_eq!(ff.payload(), b"hello");
+    }
+
+    #[test]
+    fn test_read_malformed_frame() {
+        assert!(Frame::default().read_frame(&[]).is_err());
+        assert!(Frame::default().read_frame(&[0x13; 11]).is_err());
+
+        // header len is 15 words, options are limited by 10 words
+        assert!(Frame::default().read_frame(&[0x1F; 64]).is_err());
+
+        // header len is 5 words, only one option word is present
+        assert!(Frame::default().read_frame(&[0x15; 16]).is_err());
+
+        // header len is less than the fixed header
+        assert!(Frame::default().read_frame(&[0x10; 12]).is_err());
+        assert!(Frame::default().read_frame(&[0x12; 12]).is_err());
+    }
+
+    #[test]
+    fn test_read_options_malformed_header() {
+        let mut ff = Frame::default();
+        ff.header_mut()[0] = 0x1F;
+        assert_eq!(ff.read_options(), None);
+
+        ff.header_mut()[0] = 0x15;
+        assert_eq!(ff.read_options(), None);
+    }
+
+    #[test]
+    fn test_options_api() {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        assert_eq!(ff.options().count(), 0);
+        assert!(ff.set_option(0, 1).is_err());
+
+        ff.write_options(&[1, 2, 3]).unwrap();
+        ff.push_option(4).unwrap();
+        ff.set_option(0, 10).unwrap();
+        assert!(ff.set_option(4, 1).is_err());
+        assert_eq!(ff.options().collect::<Vec<_>>(), vec![10, 2, 3, 4]);
+
+        // the whole write is rejected, not only the words past the limit
+        assert!(ff.write_options(&[5, 6, 7, 8, 9, 10, 11]).is_err());
+        assert_eq!(ff.read_options().unwrap(), vec![10, 2, 3, 4]);
+
+        ff.write_options(&[5, 6, 7, 8, 9, 10]).unwrap();
+        assert!(ff.push_option(11).is_err());
+        assert!(ff.write_options(&[]).is_err());
+        assert_eq!(ff.read_hl(), 13);
+        assert_eq!(ff.version(), 1);
+        assert_eq!(ff.options().last(), Some(10));
+    }
+
+    #[test]
+    fn test_decode_stream() {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_options(&[1, 2]).unwrap();
+        ff.write_payload(b"hello");
+        ff.write_crc();
+
+        let mut data = ff.bytes();
+        data.extend(ff.bytes());
+
+        let (res, n) = Frame::decode(&data).unwrap().unwrap();
+        assert_eq!(n, data.len() / 2);
+        assert_eq!(res, ff);
+
+        // incomplete
+        assert!(Frame::decode(&data[..n - 1]).unwrap().is_none());
+        assert!(Frame::decode(&data[..5]).unwrap().is_none());
+
+        // header len 15 is not valid
+        assert!(Frame::decode(&[0x1F; 64]).is_err());
+    }
+
+    mod proptests {
+        use crate::frame::Frame;
+        use crate::frame::frame_flags::Flags;
+        use crate::frame::header::FrameHeader;
+        use proptest::prelude::*;
+
+        fn encode(version: u8, flags: u8, options: &[u32], payload: &[u8]) -> Frame {
+            let mut ff = Frame::default();
+            ff.write_version(version);
+            ff.set_flags(Flags::from_bits_retain(flags));
+            if !options.is_empty() {
+                ff.write_options(options).unwrap();
+            }
+            ff.write_payload(payload);
+            ff.write_crc();
+            ff
+        }
+
+        proptest! {
+            #[test]
+            fn frame_round_trip(
+                version in 0u8..=15,
+                flags in any::<u8>(),
+                options in prop::collection::vec(any::<u32>(), 0..=10),
+                payload in prop::collection::vec(any::<u8>(), 0..1024),
+            ) {
+                let mut ff = encode(version, flags, &options, &payload);
+                let res = Frame::default().read_frame(&ff.bytes()).unwrap();
+
+                prop_assert_eq!(&res, &ff);
+                prop_assert!(res.verify_crc().is_ok());
+                prop_assert_eq!(res.version(), version);
+                prop_assert_eq!(res.read_flags().bits(), flags);
+                prop_assert_eq!(res.read_hl() as usize, 3 + options.len());
+                prop_assert_eq!(res.read_payload_len() as usize, payload.len());
+                prop_assert_eq!(res.payload(), &payload);
+                prop_assert_eq!(res.read_options().unwrap_or_default(), options);
+            }
+
+            #[test]
+            fn frame_options_written_in_parts(
+                first in prop::collection::vec(any::<u32>(), 1..=5),
+                second in prop::collection::vec(any::<u32>(), 1..=5),
+            ) {
+                let mut ff = Frame::default();
+                ff.write_version(1);
+                ff.write_options(&first).unwrap();
+                ff.write_options(&second).unwrap();
+                ff.write_crc();
+
+                let res = Frame::default().read_frame(&ff.bytes()).unwrap();
+                let expected: Vec<u32> = first.iter().chain(second.iter()).copied().collect();
+                prop_assert_eq!(res.read_options().unwrap(), expected);
+                prop_assert_eq!(res.version(), 1);
+            }
+
+            #[test]
+            fn header_round_trip(
+                version in 0u8..=15,
+                hl in 3u8..=13,
+                flags in any::<u8>(),
+                payload_len in any::<u32>(),
+                crc in any::<u32>(),
+            ) {
+                let header = FrameHeader {
+                    version,
+                    hl,
+                    flags: Flags::from_bits_retain(flags),
+                    payload_len,
+                    crc,
+                };
+
+                prop_assert_eq!(FrameHeader::parse(&header.to_bytes()).unwrap(), header);
+            }
+
+            #[test]
+            fn read_frame_and_decode_agree(
+                first in any::<u8>(),
+                // longer than the biggest header, only the header len could be rejected
+                rest in prop::collection::vec(any::<u8>(), 51..128),
+            ) {
+                let mut data = vec![first];
+                data.extend(rest);
+
+                prop_assert_eq!(
+                    Frame::default().read_frame(&data).is_err(),
+                    Frame::decode(&data).is_err()
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,57 @@
+pub const VERSION_1: u8 = 1;
+
+// protocol versions this crate is able to decode, from oldest to newest
+pub const SUPPORTED_VERSIONS: &[u8] = &[VERSION_1];
+
+pub fn check_version(version: u8) -> anyhow::Result<()> {
+    if SUPPORTED_VERSIONS.contains(&version) {
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "unsupported protocol version {}, supported versions: {:?}",
+        version,
+        SUPPORTED_VERSIONS
+    )
+}
+
+/// Highest version supported by both sides.
+/// An empty `remote` list means the peer predates negotiation and speaks only the first version.
+pub fn negotiate(local: &[u8], remote: &[u8]) -> anyhow::Result<u8> {
+    if remote.is_empty() {
+        return match local.contains(&VERSION_1) {
+            true => Ok(VERSION_1),
+            false => anyhow::bail!("peer supports only version {}", VERSION_1),
+        };
+    }
+
+    match local.iter().filter(|v| remote.contains(v)).max() {
+        Some(&version) => Ok(version),
+        None => anyhow::bail!(
+            "no common protocol version, local: {:?}, remote: {:?}",
+            local,
+            remote
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::version::{VERSION_1, check_version, negotiate};
+
+    #[test]
+    fn test_check_version() {
+        assert!(check_version(VERSION_1).is_ok());
+        assert!(check_version(0).is_err());
+        assert!(check_version(15).is_err());
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(&[1, 2, 3], &[1, 2]).unwrap(), 2);
+        assert_eq!(negotiate(&[1, 2], &[3, 2, 1]).unwrap(), 2);
+        assert_eq!(negotiate(&[1], &[]).unwrap(), 1);
+        assert!(negotiate(&[2], &[]).is_err());
+        assert!(negotiate(&[1], &[2, 3]).is_err());
+    }
+}
@@ -1,3 +1,14 @@
 mod bit_operations;
+pub mod buffer;
+pub mod capture;
 pub mod frame;
+pub mod metrics;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod payload;
 pub mod pipe;
+pub mod pool;
+pub mod relay;
+pub mod replay;
+mod trace;
+pub mod worker;
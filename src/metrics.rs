@@ -0,0 +1,181 @@
+// relay and worker metrics recorded via the `metrics` facade, everything is a no-op
+// without the `metrics` feature
+
+use std::time::Duration;
+
+pub const FRAMES_SENT: &str = "goridge_frames_sent_total";
+pub const FRAMES_RECEIVED: &str = "goridge_frames_received_total";
+pub const BYTES_SENT: &str = "goridge_bytes_sent_total";
+pub const BYTES_RECEIVED: &str = "goridge_bytes_received_total";
+pub const DECODE_ERRORS: &str = "goridge_decode_errors_total";
+pub const EXEC_DURATION: &str = "goridge_exec_duration_seconds";
+pub const WORKERS_SPAWNED: &str = "goridge_workers_spawned_total";
+pub const WORKERS_EXITED: &str = "goridge_workers_exited_total";
+pub const WORKERS_RESTARTED: &str = "goridge_workers_restarted_total";
+
+/// Exec latency histogram buckets in seconds.
+pub const EXEC_DURATION_BUCKETS: &[f64] = &[
+    0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0,
+];
+
+/// Relay name used as the `relay` label when not set.
+pub const DEFAULT_RELAY: &str = "default";
+
+/// Kind of the error the frames decoder failed with, the `kind` label of the decode errors.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DecodeError {
+    // stream closed or failed in the middle of the frame
+    Io,
+    HeaderCrc,
+    HeaderLen,
+    Version,
+    PayloadTooBig,
+    PayloadCrc,
+}
+
+impl DecodeError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecodeError::Io => "io",
+            DecodeError::HeaderCrc => "header_crc",
+            DecodeError::HeaderLen => "header_len",
+            DecodeError::Version => "version",
+            DecodeError::PayloadTooBig => "payload_too_big",
+            DecodeError::PayloadCrc => "payload_crc",
+        }
+    }
+}
+
+/// Registers the descriptions of the metrics in the installed recorder.
+pub fn describe() {
+    #[cfg(feature = "metrics")]
+    {
+        use ::metrics::{Unit, describe_counter, describe_histogram};
+
+        describe_counter!(FRAMES_SENT, "Frames sent by the relay");
+        describe_counter!(FRAMES_RECEIVED, "Frames received by the relay");
+        describe_counter!(BYTES_SENT, Unit::Bytes, "Bytes sent by the relay");
+        describe_counter!(BYTES_RECEIVED, Unit::Bytes, "Bytes received by the relay");
+        describe_counter!(DECODE_ERRORS, "Frames the relay failed to decode");
+        describe_histogram!(
+            EXEC_DURATION,
+            Unit::Seconds,
+            "Time between the request sent and the response received"
+        );
+        describe_counter!(WORKERS_SPAWNED, "Worker processes spawned");
+        describe_counter!(WORKERS_EXITED, "Worker processes exited");
+        describe_counter!(WORKERS_RESTARTED, "Worker processes restarted");
+    }
+}
+
+/// Metric handles of the relay, registered once per relay name so the frames don't pay
+/// for the label allocation and the registry lookup. Bound to the recorder installed
+/// at the time of creation.
+#[derive(Clone)]
+pub(crate) struct RelayMetrics {
+    #[cfg(feature = "metrics")]
+    frames_sent: ::metrics::Counter,
+    #[cfg(feature = "metrics")]
+    bytes_sent: ::metrics::Counter,
+    #[cfg(feature = "metrics")]
+    frames_received: ::metrics::Counter,
+    #[cfg(feature = "metrics")]
+    bytes_received: ::metrics::Counter,
+    #[cfg(feature = "metrics")]
+    exec_duration: ::metrics::Histogram,
+}
+
+impl RelayMetrics {
+    pub(crate) fn new(_relay: &str) -> Self {
+        #[cfg(feature = "metrics")]
+        {
+            let labels = [("relay", _relay.to_string())];
+            RelayMetrics {
+                frames_sent: ::metrics::counter!(FRAMES_SENT, &labels),
+                bytes_sent: ::metrics::counter!(BYTES_SENT, &labels),
+                frames_received: ::metrics::counter!(FRAMES_RECEIVED, &labels),
+                bytes_received: ::metrics::counter!(BYTES_RECEIVED, &labels),
+                exec_duration: ::metrics::histogram!(EXEC_DURATION, &labels),
+            }
+        }
+        #[cfg(not(feature = "metrics"))]
+        RelayMetrics {}
+    }
+
+    #[inline]
+    pub(crate) fn frame_sent(&self, _bytes: usize) {
+        #[cfg(feature = "metrics")]
+        {
+            self.frames_sent.increment(1);
+            self.bytes_sent.increment(_bytes as u64);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn frame_received(&self, _bytes: usize) {
+        #[cfg(feature = "metrics")]
+        {
+            self.frames_received.increment(1);
+            self.bytes_received.increment(_bytes as u64);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn exec_duration(&self, _elapsed: Duration) {
+        #[cfg(feature = "metrics")]
+        self.exec_duration.record(_elapsed);
+    }
+}
+
+#[inline]
+pub(crate) fn decode_error(_relay: &str, _kind: DecodeError) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(DECODE_ERRORS, "relay" => _relay.to_string(), "kind" => _kind.as_str())
+        .increment(1);
+}
+
+#[inline]
+pub(crate) fn worker_spawned() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(WORKERS_SPAWNED).increment(1);
+}
+
+#[inline]
+pub(crate) fn worker_restarted() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(WORKERS_RESTARTED).increment(1);
+}
+
+#[inline]
+pub(crate) fn worker_exited(_status: &std::process::ExitStatus) {
+    #[cfg(feature = "metrics")]
+    {
+        let status = match (_status.success(), _status.code()) {
+            (true, _) => "success",
+            (false, Some(_)) => "failure",
+            // killed by a signal
+            (false, None) => "signal",
+        };
+        ::metrics::counter!(WORKERS_EXITED, "status" => status).increment(1);
+    }
+}
+
+#[cfg(feature = "prometheus")]
+pub use metrics_exporter_prometheus::PrometheusHandle;
+
+/// Installs the global Prometheus recorder, `PrometheusHandle::render` returns the metrics
+/// in the Prometheus text format.
+#[cfg(feature = "prometheus")]
+pub fn install_prometheus() -> anyhow::Result<PrometheusHandle> {
+    use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(EXEC_DURATION.to_string()),
+            EXEC_DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    describe();
+    Ok(handle)
+}
@@ -0,0 +1,168 @@
+// W3C trace context propagation through the RoadRunner payload context, the propagator is
+// the global one (`opentelemetry::global::set_text_map_propagator`)
+
+use crate::frame::frame_flags::Flags;
+use crate::payload::Payload;
+use anyhow::anyhow;
+use opentelemetry::Context;
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use serde_json::{Map, Value};
+
+// headers ride in the context as `{"headers": {"name": ["value", ...]}}`
+const HEADERS: &str = "headers";
+
+struct HeadersInjector<'a>(&'a mut Map<String, Value>);
+
+impl Injector for HeadersInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0
+            .insert(key.to_string(), Value::Array(vec![Value::String(value)]));
+    }
+}
+
+struct HeadersExtractor<'a>(&'a Map<String, Value>);
+
+impl Extractor for HeadersExtractor<'_> {
+    // header names are case-insensitive
+    fn get(&self, key: &str) -> Option<&str> {
+        let value = self
+            .0
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)?;
+
+        match value {
+            Value::String(value) => Some(value),
+            Value::Array(values) => values.first().and_then(Value::as_str),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(String::as_str).collect()
+    }
+}
+
+fn context_json(payload: &Payload) -> anyhow::Result<Map<String, Value>> {
+    if !payload.codec.contains(Flags::CODEC_JSON) {
+        return Err(anyhow!(
+            "trace context needs the JSON payload context, codec is {:?}",
+            payload.codec
+        ));
+    }
+
+    if payload.context.is_empty() {
+        return Ok(Map::new());
+    }
+
+    match serde_json::from_slice(&payload.context)? {
+        Value::Object(context) => Ok(context),
+        _ => Err(anyhow!("payload context is not a JSON object")),
+    }
+}
+
+/// Writes the `traceparent`/`tracestate` of the `cx` into the payload context headers.
+pub fn inject(cx: &Context, payload: &mut Payload) -> anyhow::Result<()> {
+    let mut context = context_json(payload)?;
+
+    let headers = context
+        .entry(HEADERS)
+        .or_insert_with(|| Value::Object(Map::new()));
+    let Value::Object(headers) = headers else {
+        return Err(anyhow!("payload context headers are not a JSON object"));
+    };
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(cx, &mut HeadersInjector(headers))
+    });
+
+    payload.context = serde_json::to_vec(&context)?;
+    Ok(())
+}
+
+/// Reads the trace context from the payload context headers, the payloads without
+/// the headers give the empty context.
+pub fn extract(payload: &Payload) -> anyhow::Result<Context> {
+    let context = context_json(payload)?;
+
+    let headers = match context.get(HEADERS) {
+        Some(Value::Object(headers)) => headers,
+        _ => return Ok(Context::new()),
+    };
+
+    Ok(global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeadersExtractor(headers))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::frame_flags::Flags;
+    use crate::otel::{extract, inject};
+    use crate::payload::Payload;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::{Context, global};
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+    fn cx() -> Context {
+        let span = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::from_key_value([("rojo", "00f067aa0ba902b7")]).unwrap(),
+        );
+        Context::new().with_remote_span_context(span)
+    }
+
+    #[test]
+    fn test_inject_extract() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let mut payload = Payload::new(br#"{"headers":{"Content-Type":["text/plain"]}}"#, b"hello");
+        inject(&cx(), &mut payload).unwrap();
+
+        let context: serde_json::Value = serde_json::from_slice(&payload.context).unwrap();
+        assert_eq!(
+            context["headers"]["traceparent"][0],
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert_eq!(context["headers"]["tracestate"][0], "rojo=00f067aa0ba902b7");
+        assert_eq!(context["headers"]["Content-Type"][0], "text/plain");
+
+        let extracted = extract(&payload).unwrap();
+        let span = extracted.span().span_context().clone();
+        assert_eq!(span.trace_id(), cx().span().span_context().trace_id());
+        assert_eq!(span.span_id(), cx().span().span_context().span_id());
+        assert_eq!(span.trace_state().header(), "rojo=00f067aa0ba902b7");
+        assert!(span.is_remote());
+
+        // empty context gets the headers
+        let mut payload = Payload::new(b"", b"hello");
+        inject(&cx(), &mut payload).unwrap();
+        assert!(extract(&payload).unwrap().span().span_context().is_valid());
+
+        // PHP side could send the single value and the different case
+        let payload = Payload::new(
+            br#"{"headers":{"Traceparent":"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"}}"#,
+            b"",
+        );
+        assert!(extract(&payload).unwrap().span().span_context().is_valid());
+
+        let payload = Payload::new(br#"{"queue":"default"}"#, b"");
+        assert!(!extract(&payload).unwrap().span().span_context().is_valid());
+    }
+
+    #[test]
+    fn test_non_json_context() {
+        let mut payload = Payload::new(b"\x01\x02", b"hello");
+        payload.codec = Flags::CODEC_PROTO;
+        assert!(inject(&cx(), &mut payload).is_err());
+
+        let payload = Payload::new(b"[1, 2]", b"hello");
+        assert!(extract(&payload).is_err());
+    }
+}
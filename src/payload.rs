@@ -0,0 +1,89 @@
+use crate::frame::Frame;
+use crate::frame::frame_flags::Flags;
+use anyhow::anyhow;
+
+/// RoadRunner payload: the context (headers, job options, encoded with the `codec`)
+/// followed by the body in the frame payload, the first option is the context len.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Payload {
+    pub codec: Flags,
+    pub context: Vec<u8>,
+    pub body: Vec<u8>,
+}
+
+impl Payload {
+    pub fn new(context: &[u8], body: &[u8]) -> Self {
+        Payload {
+            codec: Flags::CODEC_JSON,
+            context: context.to_vec(),
+            body: body.to_vec(),
+        }
+    }
+
+    pub fn to_frame(&self, version: u8) -> anyhow::Result<Frame> {
+        let mut frame = Frame::default();
+        frame.write_version(version);
+        frame.set_flags(self.codec);
+        frame.write_options(&[self.context.len() as u32])?;
+        frame.write_payload(&self.context);
+        frame.write_payload(&self.body);
+        frame.write_crc();
+        Ok(frame)
+    }
+
+    /// Frames without the options have no context.
+    pub fn from_frame(frame: &Frame) -> anyhow::Result<Self> {
+        let data = frame.payload();
+        let context_len = frame.options().next().unwrap_or(0) as usize;
+        if context_len > data.len() {
+            return Err(anyhow!(
+                "context len {} is bigger than the payload len {}",
+                context_len,
+                data.len()
+            ));
+        }
+
+        Ok(Payload {
+            codec: frame.read_flags() & Flags::CODECS,
+            context: data[..context_len].to_vec(),
+            body: data[context_len..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::Frame;
+    use crate::frame::frame_flags::{Flag, Flags};
+    use crate::payload::Payload;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let payload = Payload::new(br#"{"headers":{}}"#, b"hello");
+
+        let mut frame = payload.to_frame(1).unwrap();
+        assert_eq!(frame.read_options().unwrap(), vec![14]);
+        assert_eq!(frame.read_flags(), Flags::CODEC_JSON);
+
+        let frame = Frame::try_from(frame.bytes()).unwrap();
+        assert_eq!(Payload::from_frame(&frame).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_payload_from_frame() {
+        let mut frame = Frame::default();
+        frame.write_version(1);
+        frame.write_flags(&[Flag::Error, Flag::CodecRaw]);
+        frame.write_payload(b"hello");
+        frame.write_crc();
+
+        // no options, no context
+        let payload = Payload::from_frame(&frame).unwrap();
+        assert_eq!(payload.codec, Flags::CODEC_RAW);
+        assert!(payload.context.is_empty());
+        assert_eq!(payload.body, b"hello");
+
+        frame.write_options(&[6]).unwrap();
+        assert!(Payload::from_frame(&frame).is_err());
+    }
+}
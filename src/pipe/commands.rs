@@ -1,17 +1,22 @@
-#![allow(dead_code)]
-
-use crate::pipe::Marshaller;
+use crate::frame::Frame;
+use crate::frame::frame_flags::Flags;
+use crate::frame::version::SUPPORTED_VERSIONS;
+use crate::relay::Marshaller;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PidCommand {
     pub pid: u32,
+    // protocol versions supported by the sender, absent for the legacy workers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<u8>,
 }
 
 impl Default for PidCommand {
     fn default() -> Self {
         Self {
             pid: std::process::id(),
+            versions: SUPPORTED_VERSIONS.to_vec(),
         }
     }
 }
@@ -25,9 +30,9 @@ impl Marshaller for PidCommand {
     }
 }
 
-#[derive(Serialize, Default)]
-struct StopCommand {
-    stop: bool,
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct StopCommand {
+    pub stop: bool,
 }
 
 impl Marshaller for StopCommand {
@@ -39,3 +44,55 @@ impl Marshaller for StopCommand {
         }
     }
 }
+
+/// Asks the worker to abort the job in flight, the worker replies with the same command
+/// once the job is aborted or when there was no job anymore.
+/// Not a part of the goridge/RoadRunner control protocol: only the workers implementing it
+/// acknowledge it, the RoadRunner PHP worker fails on the unknown control package and exits
+/// once its job is done.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CancelCommand {
+    pub cancel: bool,
+}
+
+impl Marshaller for CancelCommand {
+    fn marshal(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.cancel = true;
+        match serde_json::to_vec(self) {
+            Ok(data) => Ok(data),
+            Err(error) => Err(anyhow::Error::msg(error.to_string())),
+        }
+    }
+}
+
+/// Control command received by the worker side of the relay.
+#[derive(Debug)]
+pub enum ControlCommand {
+    Pid(PidCommand),
+    Stop,
+    Cancel,
+}
+
+impl ControlCommand {
+    pub fn parse(frame: &Frame) -> anyhow::Result<Self> {
+        if !frame.read_flags().contains(Flags::CONTROL) {
+            anyhow::bail!("not a control frame, no CONTROL flag");
+        }
+
+        let value: serde_json::Value = serde_json::from_slice(frame.payload())?;
+
+        if value.get("pid").is_some() {
+            return Ok(ControlCommand::Pid(serde_json::from_value(value)?));
+        }
+
+        if value.get("stop").and_then(|s| s.as_bool()) == Some(true) {
+            return Ok(ControlCommand::Stop);
+        }
+
+        if value.get("cancel").and_then(|s| s.as_bool()) == Some(true) {
+            return Ok(ControlCommand::Cancel);
+        }
+
+        anyhow::bail!("unknown control command: {}", value)
+    }
+}
@@ -1,34 +1,64 @@
-mod commands;
-
-use crate::frame::frame_flags::Flag::{CodecJSON, Control};
-use crate::frame::{Frame, WORD};
-use crate::pipe::commands::PidCommand;
+pub mod commands;
+
+use crate::buffer::BufferPool;
+use crate::frame::Frame;
+use crate::frame::frame_flags::Flags;
+use crate::frame::integrity::Integrity;
+use crate::metrics;
+use crate::payload::Payload;
+use crate::relay::StreamRelay;
+use crate::trace::{Elapsed, record};
 use anyhow::anyhow;
-use std::process::Stdio;
-use std::str::from_utf8;
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, ChildStderr, Command};
-use tokio::time::{Duration, timeout};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+
+pub use crate::relay::{FrameReader, Marshaller};
 
 pub struct Pipes {
     child: Child,
-}
-
-pub trait Marshaller {
-    fn marshal(&mut self) -> anyhow::Result<Vec<u8>>;
+    relay: StreamRelay<ChildStdout, ChildStdin>,
+    // set once the process is reaped
+    status: Option<ExitStatus>,
 }
 
 impl Pipes {
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "pipe.send",
+        level = "debug",
+        skip_all,
+        err,
+        fields(pid = self.child.id(), flags, payload_len, crc, elapsed_us),
+    ))]
     pub async fn send(&mut self, frame: &mut Frame) -> anyhow::Result<()> {
-        let stdin = self.child.stdin.as_mut();
+        let _elapsed = Elapsed::start();
+        self.relay.send(frame).await
+    }
 
-        match stdin {
-            None => Err(anyhow!("no stdin")),
-            Some(child) => {
-                child.write_all(&frame.bytes()).await?;
-                Ok(())
-            }
-        }
+    /// Received frames draw their buffers from the pool and return them on drop.
+    pub fn set_buffer_pool(&mut self, pool: Arc<BufferPool>) {
+        self.relay.set_buffer_pool(pool);
+    }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.relay.version()
+    }
+
+    /// Both the parent and the worker should use the same integrity mode.
+    pub fn set_integrity(&mut self, integrity: Integrity) {
+        self.relay.set_integrity(integrity);
+    }
+
+    #[inline]
+    pub fn integrity(&self) -> Integrity {
+        self.relay.integrity()
+    }
+
+    /// Name of the relay, the `relay` label of the metrics.
+    pub fn set_name(&mut self, name: &str) {
+        self.relay.set_name(name);
     }
 
     pub fn stderr(&mut self) -> Option<&mut ChildStderr> {
@@ -53,103 +83,121 @@ impl Pipes {
         }
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "pipe.receive_stdout",
+        level = "debug",
+        skip_all,
+        err,
+        fields(pid = self.child.id(), flags, payload_len, crc, elapsed_us),
+    ))]
     pub async fn receive_stdout(&mut self) -> anyhow::Result<Frame> {
-        let stdout = self.child.stdout.as_mut();
-        match stdout {
-            None => Err(anyhow!("no data, process is possibly dead")),
-
-            Some(child) => {
-                let mut buf = BufReader::new(child);
-                let mut fr = Frame::default();
-
-                // read-only header, 12 bytes
-                buf.read_exact(fr.header_mut()).await?;
-
-                // we have an option
-                if fr.read_hl() > 3 {
-                    let opts_len = (fr.read_hl() - 3) * WORD;
-                    let mut tmp = vec![0; opts_len as usize];
-                    buf.read_exact(&mut tmp).await?;
-
-                    fr.extend_header(&tmp);
-                }
-
-                if fr.verify_crc().is_err() {
-                    let mut buffer = vec![];
-                    let timeout_dur = Duration::from_secs(2);
-                    _ = timeout(timeout_dur, buf.read_to_end(&mut buffer)).await;
-
-                    // TODO: handle error match here!
-                    let msg = match from_utf8(fr.header()) {
-                        Ok(m) => String::from(m),
-                        Err(_) => String::new(),
-                    };
-
-                    let bufmsg = match from_utf8(&buffer) {
-                        Ok(m) => String::from(m),
-                        Err(_) => String::new(),
-                    };
-
-                    return Err(anyhow!(
-                        "validation failed on the message sent to STDOUT, cause {}{}",
-                        msg,
-                        bufmsg
-                    ));
-                }
-
-                let pld_len = fr.read_payload_len();
-                if pld_len == 0 {
-                    return Ok(fr);
-                }
-
-                buf.read_exact(fr.init_payload_mut(pld_len as usize))
-                    .await?;
-
-                Ok(fr)
-            }
-        }
+        let _elapsed = Elapsed::start();
+        self.relay.receive().await
     }
 
-    pub async fn send_control<T: Marshaller>(&mut self, mut payload: T) -> anyhow::Result<()> {
-        let mut frame = Frame::default();
-
-        frame.write_version(1);
-        frame.write_flags(&[Control, CodecJSON]);
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "pipe.exec",
+        level = "debug",
+        skip_all,
+        err,
+        fields(pid = self.child.id(), flags, payload_len, crc, elapsed_us),
+    ))]
+    pub async fn exec(&mut self, frame: &mut Frame) -> anyhow::Result<Frame> {
+        let _elapsed = Elapsed::start();
+        self.relay.exec(frame).await
+    }
 
-        let data = payload.marshal()?;
-        // we don't need to Borrow the data here
-        frame.write_payload(&data);
-        frame.write_crc();
+    pub async fn send_payload(&mut self, payload: &Payload) -> anyhow::Result<()> {
+        let mut frame = payload.to_frame(self.version())?;
+        self.send(&mut frame).await
+    }
 
-        if let Some(socket_new) = self.child.stdin.as_mut() {
-            let data = frame.bytes();
-            socket_new.write_all(&data).await?;
-            return Ok(());
+    /// Frames with the ERROR flag are returned as the error with the worker message.
+    pub async fn receive_payload(&mut self) -> anyhow::Result<Payload> {
+        let frame = self.receive_stdout().await?;
+        if frame.read_flags().contains(Flags::ERROR) {
+            return Err(anyhow!(
+                "worker error: {}",
+                String::from_utf8_lossy(frame.payload())
+            ));
         }
 
-        Err(anyhow!("get None child stdin out from the option"))
+        Payload::from_frame(&frame)
     }
 
-    pub async fn send_pid(&mut self) -> anyhow::Result<u32> {
-        self.send_control(PidCommand::default()).await?;
+    /// Sends the payload with the trace context of `cx` in the payload context headers.
+    #[cfg(feature = "otel")]
+    pub async fn send_payload_with_context(
+        &mut self,
+        mut payload: Payload,
+        cx: &opentelemetry::Context,
+    ) -> anyhow::Result<()> {
+        crate::otel::inject(cx, &mut payload)?;
+        self.send_payload(&payload).await
+    }
 
-        let f = self.receive_stdout().await?;
+    /// Receives the payload and the trace context the worker sent in the payload context headers.
+    #[cfg(feature = "otel")]
+    pub async fn receive_payload_with_context(
+        &mut self,
+    ) -> anyhow::Result<(Payload, opentelemetry::Context)> {
+        let payload = self.receive_payload().await?;
+        let cx = crate::otel::extract(&payload)?;
+        Ok((payload, cx))
+    }
 
-        let flags = f.read_flags();
-        if flags & (Control as u8) == 0 {
-            return Err(anyhow!(
-                "unexpected response, header is missing, no CONTROL flag"
-            ));
-        }
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "pipe.send_control",
+        level = "debug",
+        skip_all,
+        err,
+        fields(pid = self.child.id(), flags, payload_len, crc, elapsed_us),
+    ))]
+    pub async fn send_control<T: Marshaller>(&mut self, payload: T) -> anyhow::Result<()> {
+        let _elapsed = Elapsed::start();
+        self.relay.send_control(payload).await
+    }
+
+    /// Cancels the job in flight, see `StreamRelay::cancel`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "pipe.cancel",
+        level = "debug",
+        skip_all,
+        err,
+        fields(pid = self.child.id(), discarded, elapsed_us),
+    ))]
+    pub async fn cancel(&mut self) -> anyhow::Result<usize> {
+        let _elapsed = Elapsed::start();
+        let discarded = self.relay.cancel().await?;
+        record!("discarded", discarded);
+        Ok(discarded)
+    }
 
-        let payload = f.payload();
-        let res: PidCommand = serde_json::from_slice(payload)?;
+    /// The send was dropped in the middle of the frame, the worker can't be used anymore.
+    #[inline]
+    pub fn write_interrupted(&self) -> bool {
+        self.relay.write_interrupted()
+    }
 
-        if res.pid == 0 {
-            return Err(anyhow!("pid should be greater than 0"));
-        }
+    /// The receive was dropped in the middle of the frame, the worker can't be used anymore.
+    #[inline]
+    pub fn read_interrupted(&self) -> bool {
+        self.relay.read_interrupted()
+    }
 
-        Ok(res.pid)
+    // the span covers the PID request and response, the fields are of the response
+    #[cfg_attr(feature = "tracing", tracing::instrument(
+        name = "pipe.send_pid",
+        level = "debug",
+        skip_all,
+        err,
+        fields(pid = self.child.id(), flags, payload_len, crc, version, elapsed_us),
+    ))]
+    pub async fn send_pid(&mut self) -> anyhow::Result<u32> {
+        let _elapsed = Elapsed::start();
+        let pid = self.relay.send_pid().await?;
+        record!("version", self.relay.version());
+        Ok(pid)
     }
 
     pub async fn id(&mut self) -> anyhow::Result<u32> {
@@ -160,66 +208,73 @@ impl Pipes {
         Err(anyhow!("get None child id out from the option"))
     }
 
-    pub async fn kill(&mut self) -> anyhow::Result<()> {
+    /// Kills the process and returns the exit status, the signal on unix.
+    pub async fn kill(&mut self) -> anyhow::Result<ExitStatus> {
         self.child.kill().await?;
-        Ok(())
+        self.wait().await
     }
 
-    pub async fn try_wait(&mut self) -> anyhow::Result<Option<std::process::ExitStatus>> {
+    pub async fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>> {
         match self.child.try_wait()? {
-            Some(status) => Ok(Some(status)),
+            Some(status) => {
+                self.exited(status);
+                Ok(Some(status))
+            }
             None => Ok(None),
         }
     }
 
-    pub async fn wait(&mut self) -> anyhow::Result<()> {
-        self.child.wait().await?;
-        Ok(())
+    pub async fn wait(&mut self) -> anyhow::Result<ExitStatus> {
+        let status = self.child.wait().await?;
+        self.exited(status);
+        Ok(status)
+    }
+
+    /// Exit status, known once the process is reaped by `wait` or `try_wait`.
+    #[inline]
+    pub fn status(&self) -> Option<ExitStatus> {
+        self.status
+    }
+
+    fn exited(&mut self, status: ExitStatus) {
+        if self.status.is_none() {
+            metrics::worker_exited(&status);
+            self.status = Some(status);
+        }
     }
 }
 
 impl Pipes {
     pub async fn new(cmd: &[&str]) -> anyhow::Result<Self> {
-        let command = Command::new(cmd[0])
-            .args(&cmd[1..])
+        let mut command = Command::new(cmd[0]);
+        command.args(&cmd[1..]);
+        Pipes::from_command(command).await
+    }
+
+    /// Spawns the configured command (env, working dir), stdio is always piped.
+    pub async fn from_command(mut command: Command) -> anyhow::Result<Self> {
+        let mut command = command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()?;
 
-        Ok(Pipes { child: command })
-    }
-}
-
-mod tests {
-    #[tokio::test]
-    async fn test1() {
-        use crate::frame::Frame;
-        use crate::pipe::Pipes;
-
-        let mut p = Pipes::new(&["php", "tests/worker.php"]).await.unwrap();
-        let mut frame = Frame::default();
-        let payload = vec![b'h', b'e', b'l', b'l', b'o'];
+        let stdout = match command.stdout.take() {
+            Some(stdout) => stdout,
+            None => return Err(anyhow!("get None child stdout out from the option")),
+        };
 
-        frame.write_version(1);
-        frame.write_flags(&[]);
-        frame.write_options(&[0]);
-        frame.write_payload(&payload);
-        frame.write_crc();
+        let stdin = match command.stdin.take() {
+            Some(stdin) => stdin,
+            None => return Err(anyhow!("get None child stdin out from the option")),
+        };
 
-        p.send(&mut frame).await.unwrap();
+        metrics::worker_spawned();
 
-        match p.receive_stdout().await {
-            Ok(data) => {
-                assert_eq!(data.payload(), &payload);
-            }
-            Err(error) => {
-                assert_eq!(
-                    error.to_string(),
-                    "validation failed on the message sent to STDOUT, cause warning: some weird php error, THIS IS PHP, I'm THE KING :) \u{14}\0\u{5}\0\0\0\u{1c}\u{11}[\u{1e}\0\0\0\0\0\0hello"
-                );
-                println!("{:?}", error.to_string());
-            }
-        }
+        Ok(Pipes {
+            child: command,
+            relay: StreamRelay::new(stdout, stdin),
+            status: None,
+        })
     }
 }
@@ -0,0 +1,44 @@
+use crate::worker::{Spec, Worker};
+use anyhow::anyhow;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+
+/// Spawns the workers from the spec, at most `max_concurrent` spawns and handshakes are in flight.
+#[derive(Debug, Clone)]
+pub struct Allocator {
+    spec: Spec,
+    timeout: Duration,
+    permits: Arc<Semaphore>,
+}
+
+impl Allocator {
+    pub fn new(spec: Spec, max_concurrent: usize, timeout: Duration) -> Self {
+        Allocator {
+            spec,
+            timeout,
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+        }
+    }
+
+    #[inline]
+    pub fn spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    /// Spawns the worker and does the PID handshake within the allocate timeout,
+    /// the time waiting for the spawn slot is not counted. The process is killed on the timeout.
+    pub async fn allocate(&self) -> anyhow::Result<Worker> {
+        let _permit = self.permits.acquire().await?;
+
+        match timeout(self.timeout, Worker::from_spec(&self.spec)).await {
+            Ok(worker) => worker,
+            Err(_) => Err(anyhow!(
+                "worker allocation timed out after {:?}, command: {}",
+                self.timeout,
+                self.spec.command.join(" ")
+            )),
+        }
+    }
+}
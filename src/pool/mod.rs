@@ -0,0 +1,717 @@
+mod allocator;
+mod scaler;
+mod scheduler;
+
+use crate::frame::Frame;
+use crate::worker::{
+    Backoff, BreakerState, CircuitBreaker, CrashLoop, DEFAULT_CANCEL_TIMEOUT, Spec, State, Worker,
+};
+use anyhow::anyhow;
+use scheduler::Scheduler;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinSet;
+use tokio::time::{Instant, sleep};
+
+pub use allocator::Allocator;
+pub use scheduler::ExecOptions;
+
+pub const DEFAULT_ALLOCATE_TIMEOUT: Duration = Duration::from_secs(60);
+pub const DEFAULT_MAX_CONCURRENT_SPAWNS: usize = 8;
+pub const DEFAULT_QUEUE_WAIT: Duration = Duration::from_millis(100);
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+pub const DEFAULT_SPAWN_RATE: usize = 5;
+
+/// Pool settings, `min_ready` of 0 waits for all the workers.
+/// The pool is scaled above `num_workers` only when `max_workers` is greater.
+/// The failed allocations are retried with the `backoff`, the `circuit_breaker` fails the waiting
+/// execs once the allocations keep failing and no worker is left.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub spec: Spec,
+    pub num_workers: usize,
+    // workers passed the PID handshake before the pool is ready
+    pub min_ready: usize,
+    pub max_concurrent_spawns: usize,
+    // spawn and PID handshake of the single worker
+    pub allocate_timeout: Duration,
+    pub max_workers: usize,
+    // the extra worker is spawned when the job waits longer for the worker
+    pub queue_wait: Duration,
+    // the extra worker is stopped when idle for longer, no sooner than that after the last scale up
+    pub idle_timeout: Duration,
+    // extra workers spawned per second at most
+    pub spawn_rate: usize,
+    // share of the workers for the tenant of the same priority, 1 when not set
+    pub tenant_weights: HashMap<String, u32>,
+    // the worker not acknowledging the cancel of the dropped exec is recycled after,
+    // the workers without the cancel command support never acknowledge it
+    pub cancel_timeout: Duration,
+    pub backoff: Backoff,
+    pub circuit_breaker: CircuitBreaker,
+}
+
+impl Config {
+    pub fn new(spec: Spec, num_workers: usize) -> Self {
+        Config {
+            spec,
+            num_workers,
+            min_ready: 0,
+            max_concurrent_spawns: DEFAULT_MAX_CONCURRENT_SPAWNS,
+            allocate_timeout: DEFAULT_ALLOCATE_TIMEOUT,
+            max_workers: 0,
+            queue_wait: DEFAULT_QUEUE_WAIT,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            spawn_rate: DEFAULT_SPAWN_RATE,
+            tenant_weights: HashMap::new(),
+            cancel_timeout: DEFAULT_CANCEL_TIMEOUT,
+            backoff: Backoff::default(),
+            circuit_breaker: CircuitBreaker::default(),
+        }
+    }
+
+    pub fn set_min_ready(&mut self, min_ready: usize) {
+        self.min_ready = min_ready;
+    }
+
+    pub fn set_max_concurrent_spawns(&mut self, max_concurrent_spawns: usize) {
+        self.max_concurrent_spawns = max_concurrent_spawns;
+    }
+
+    pub fn set_allocate_timeout(&mut self, allocate_timeout: Duration) {
+        self.allocate_timeout = allocate_timeout;
+    }
+
+    pub fn set_max_workers(&mut self, max_workers: usize) {
+        self.max_workers = max_workers;
+    }
+
+    pub fn set_queue_wait(&mut self, queue_wait: Duration) {
+        self.queue_wait = queue_wait;
+    }
+
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    pub fn set_spawn_rate(&mut self, spawn_rate: usize) {
+        self.spawn_rate = spawn_rate;
+    }
+
+    pub fn set_tenant_weight(&mut self, tenant: &str, weight: u32) {
+        self.tenant_weights.insert(tenant.to_string(), weight);
+    }
+
+    pub fn set_cancel_timeout(&mut self, cancel_timeout: Duration) {
+        self.cancel_timeout = cancel_timeout;
+    }
+
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    pub fn set_circuit_breaker(&mut self, circuit_breaker: CircuitBreaker) {
+        self.circuit_breaker = circuit_breaker;
+    }
+
+    fn is_dynamic(&self) -> bool {
+        self.max_workers > self.num_workers && self.spawn_rate > 0
+    }
+
+    fn min_ready(&self) -> usize {
+        match self.min_ready {
+            0 => self.num_workers,
+            n => n.min(self.num_workers),
+        }
+    }
+}
+
+// initial allocation progress
+#[derive(Debug, Clone, Default)]
+struct Progress {
+    ready: usize,
+    failed: usize,
+    error: Option<String>,
+}
+
+// every worker holds the token of its generation, the generation is drained
+// once all the tokens are dropped
+type Token = mpsc::Sender<()>;
+
+struct Pooled {
+    worker: Worker,
+    generation: u64,
+    idle_since: Instant,
+    _token: Token,
+}
+
+impl Pooled {
+    fn new(worker: Worker, generation: u64, token: Token) -> Self {
+        Pooled {
+            worker,
+            generation,
+            idle_since: Instant::now(),
+            _token: token,
+        }
+    }
+}
+
+struct Workers {
+    idle: VecDeque<Pooled>,
+    // execs waiting for the worker
+    waiters: Scheduler<oneshot::Sender<Pooled>>,
+    // incremented by the reset
+    generation: u64,
+    token: Token,
+    drained: mpsc::Receiver<()>,
+    // failed allocations
+    crash_loop: CrashLoop,
+    closed: bool,
+}
+
+impl Workers {
+    fn new(weights: HashMap<String, u32>) -> Self {
+        let (token, drained) = mpsc::channel(1);
+        Workers {
+            idle: VecDeque::new(),
+            waiters: Scheduler::new(weights),
+            generation: 0,
+            token,
+            drained,
+            crash_loop: CrashLoop::new(),
+            closed: false,
+        }
+    }
+
+    // hands the worker to the next scheduled waiter or keeps it idle, returned back to be stopped
+    // when the pool is stopped or the worker is from before the reset
+    fn push(&mut self, mut pooled: Pooled) -> Option<Pooled> {
+        if self.closed || pooled.generation != self.generation {
+            return Some(pooled);
+        }
+
+        // the execs dropped while waiting are skipped
+        while let Some(waiter) = self.waiters.pop(|waiter| !waiter.is_closed()) {
+            match waiter.send(pooled) {
+                Ok(()) => return None,
+                // the exec was dropped right now
+                Err(returned) => pooled = returned,
+            }
+        }
+
+        pooled.idle_since = Instant::now();
+        self.idle.push_back(pooled);
+        None
+    }
+
+    // the execs dropped while waiting, not to be counted by the scaler
+    fn prune_waiters(&mut self) {
+        self.waiters.retain(|waiter| !waiter.is_closed());
+    }
+
+    // current generation workers, including the ones being allocated
+    fn alive(&self) -> usize {
+        // the own token is not counted
+        self.token.strong_count() - 1
+    }
+
+    // no worker could come until the breaker cooldown ends
+    fn unavailable(&self) -> bool {
+        self.crash_loop.state() == BreakerState::Open && self.alive() == 0
+    }
+}
+
+struct Inner {
+    allocator: Allocator,
+    workers: Mutex<Workers>,
+    progress: watch::Sender<Progress>,
+    cancel_timeout: Duration,
+    backoff: Backoff,
+    breaker: CircuitBreaker,
+}
+
+impl Inner {
+    fn release(&self, pooled: Pooled) -> Option<Pooled> {
+        self.workers.lock().unwrap().push(pooled)
+    }
+
+    // token of the worker allocated for the `generation`, none once the pool is stopped or reset
+    fn token(&self, generation: u64) -> Option<Token> {
+        let workers = self.workers.lock().unwrap();
+        match workers.closed || workers.generation != generation {
+            true => None,
+            false => Some(workers.token.clone()),
+        }
+    }
+
+    fn is_current(&self, generation: u64) -> bool {
+        self.workers.lock().unwrap().generation == generation
+    }
+
+    fn unavailable_error(&self, workers: &Workers) -> anyhow::Error {
+        anyhow!(
+            "no worker is available, circuit breaker is open: {} failed allocations in {:?}, last error: {}",
+            workers.crash_loop.crashes(),
+            self.breaker.window,
+            self.progress.borrow().error.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+/// Pool of the workers spawned from the same spec, the jobs are executed on the idle workers.
+/// The workers are allocated in parallel in the background, `wait_ready` waits for `min_ready`
+/// of them. Dropping the pool kills all the workers.
+pub struct Pool {
+    config: Config,
+    inner: Arc<Inner>,
+    tasks: Mutex<JoinSet<()>>,
+    // one reset at a time
+    reset: tokio::sync::Mutex<()>,
+}
+
+impl Pool {
+    /// Starts the allocation and waits until the pool is ready.
+    pub async fn start(config: Config) -> anyhow::Result<Self> {
+        let pool = Pool::new(config);
+        pool.wait_ready().await?;
+        Ok(pool)
+    }
+
+    /// Starts the allocation of all the workers in the background, must be called within the runtime.
+    pub fn new(config: Config) -> Self {
+        let allocator = Allocator::new(
+            config.spec.clone(),
+            config.max_concurrent_spawns,
+            config.allocate_timeout,
+        );
+
+        let pool = Pool {
+            inner: Arc::new(Inner {
+                allocator,
+                workers: Mutex::new(Workers::new(config.tenant_weights.clone())),
+                progress: watch::Sender::new(Progress::default()),
+                cancel_timeout: config.cancel_timeout,
+                backoff: config.backoff,
+                breaker: config.circuit_breaker,
+            }),
+            tasks: Mutex::new(JoinSet::new()),
+            reset: tokio::sync::Mutex::new(()),
+            config,
+        };
+
+        for _ in 0..pool.config.num_workers {
+            pool.spawn(allocate(pool.inner.clone(), true));
+        }
+
+        if pool.config.is_dynamic() {
+            pool.spawn(scaler::scale(pool.inner.clone(), pool.config.clone()));
+        }
+
+        pool
+    }
+
+    #[inline]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// `min_ready` workers passed the PID handshake.
+    pub fn is_ready(&self) -> bool {
+        self.inner.progress.borrow().ready >= self.config.min_ready()
+    }
+
+    /// Waits for `min_ready` workers, fails once too many allocations failed to get them.
+    /// The failed allocation is retried and counted as failed only while the circuit breaker is open.
+    pub async fn wait_ready(&self) -> anyhow::Result<()> {
+        let min_ready = self.config.min_ready();
+        let num_workers = self.config.num_workers;
+
+        let mut rx = self.inner.progress.subscribe();
+        let progress = rx
+            .wait_for(|p| p.ready >= min_ready || num_workers - p.failed < min_ready)
+            .await?
+            .clone();
+
+        if progress.ready >= min_ready {
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "pool is not ready: {} of {} workers failed to allocate, {} required, last error: {}",
+            progress.failed,
+            num_workers,
+            min_ready,
+            progress.error.unwrap_or_default()
+        ))
+    }
+
+    /// Number of the workers, including the ones being allocated and the extra ones.
+    pub fn workers(&self) -> usize {
+        self.inner.workers.lock().unwrap().alive()
+    }
+
+    /// Number of the workers waiting for the job.
+    pub fn idle_workers(&self) -> usize {
+        self.inner.workers.lock().unwrap().idle.len()
+    }
+
+    /// Number of the execs waiting for the worker.
+    pub fn waiting_jobs(&self) -> usize {
+        let mut workers = self.inner.workers.lock().unwrap();
+        workers.prune_waiters();
+        workers.waiters.len()
+    }
+
+    /// Number of the resets, incremented once the fresh workers took over.
+    pub fn generation(&self) -> u64 {
+        self.inner.workers.lock().unwrap().generation
+    }
+
+    /// Executes the job on the idle worker, waits for one when all are busy.
+    /// The worker failed the job is replaced in the background.
+    pub async fn exec(&self, frame: &mut Frame) -> anyhow::Result<Frame> {
+        self.exec_with(frame, &ExecOptions::default()).await
+    }
+
+    /// Executes the job with the priority and the tenant, they order the jobs
+    /// waiting for the worker. Dropping the future in the middle of the job cancels it,
+    /// see `Worker::cancel`.
+    pub async fn exec_with(
+        &self,
+        frame: &mut Frame,
+        options: &ExecOptions,
+    ) -> anyhow::Result<Frame> {
+        let pooled = self.acquire(options).await?;
+        let mut lease = Lease {
+            inner: self.inner.clone(),
+            pooled: Some(pooled),
+        };
+
+        let result = lease.pooled.as_mut().unwrap().worker.exec(frame).await;
+        let pooled = lease.pooled.take().unwrap();
+
+        match pooled.worker.state() {
+            State::Ready => self.release(pooled).await,
+            _ => self.replace(pooled),
+        }
+
+        result
+    }
+
+    /// Replaces all the workers with the fresh ones, e.g. to load the new code.
+    /// The new workers are allocated first, then the idle old workers are stopped with the stop
+    /// command and the busy ones once their jobs are done, the pool keeps serving throughout.
+    /// Returns when all the old workers are stopped, an allocation failure keeps the old workers.
+    pub async fn reset(&self) -> anyhow::Result<()> {
+        let _reset = self.reset.lock().await;
+
+        let (token, drained) = mpsc::channel(1);
+        let generation = self.generation() + 1;
+
+        let mut allocations = JoinSet::new();
+        for _ in 0..self.config.num_workers {
+            let allocator = self.inner.allocator.clone();
+            allocations.spawn(async move { allocator.allocate().await });
+        }
+
+        let mut fresh = Vec::with_capacity(self.config.num_workers);
+        while let Some(worker) = allocations.join_next().await {
+            match worker? {
+                Ok(worker) => fresh.push(worker),
+                // the allocated ones are killed on drop
+                Err(err) => return Err(err.context("pool reset failed")),
+            }
+        }
+
+        let (mut drained, old) = {
+            let mut workers = self.inner.workers.lock().unwrap();
+            if workers.closed {
+                return Err(anyhow!("pool is stopped"));
+            }
+
+            workers.generation = generation;
+            // the old token is dropped at the end of the block
+            let _old_token = std::mem::replace(&mut workers.token, token);
+            let drained = std::mem::replace(&mut workers.drained, drained);
+            let old = std::mem::take(&mut workers.idle);
+
+            for worker in fresh {
+                let pooled = Pooled::new(worker, generation, workers.token.clone());
+                workers.push(pooled);
+            }
+
+            (drained, old)
+        };
+
+        for pooled in old {
+            self.spawn(stop(pooled));
+        }
+
+        // all the old workers are stopped, the busy ones are stopped on release
+        drained.recv().await;
+        Ok(())
+    }
+
+    /// Stops the idle workers, the busy ones are stopped once their jobs are done.
+    /// The waiting execs fail.
+    pub async fn stop(&self) -> anyhow::Result<()> {
+        let idle = {
+            let mut workers = self.inner.workers.lock().unwrap();
+            workers.closed = true;
+            workers.waiters.clear();
+            std::mem::take(&mut workers.idle)
+        };
+
+        let mut stops = JoinSet::new();
+        for mut pooled in idle {
+            stops.spawn(async move { pooled.worker.stop().await });
+        }
+
+        let mut result = Ok(());
+        while let Some(stopped) = stops.join_next().await {
+            if let Err(err) = stopped? {
+                result = Err(err);
+            }
+        }
+
+        result
+    }
+
+    async fn acquire(&self, options: &ExecOptions) -> anyhow::Result<Pooled> {
+        loop {
+            let rx = {
+                let mut workers = self.inner.workers.lock().unwrap();
+                if workers.closed {
+                    return Err(anyhow!("pool is stopped"));
+                }
+
+                let (tx, rx) = oneshot::channel();
+                // the most recently used first, the extra workers are left idle to be reaped
+                match workers.idle.pop_back() {
+                    Some(worker) => _ = tx.send(worker),
+                    None if workers.unavailable() => {
+                        return Err(self.inner.unavailable_error(&workers));
+                    }
+                    None => workers.waiters.push(options, tx),
+                }
+                rx
+            };
+
+            let mut waiting = Waiting {
+                inner: self.inner.clone(),
+                rx,
+            };
+            let Ok(mut pooled) = (&mut waiting.rx).await else {
+                // dropped by the stop or when no worker could be allocated
+                let workers = self.inner.workers.lock().unwrap();
+                return match workers.closed {
+                    true => Err(anyhow!("pool is stopped")),
+                    false => Err(self.inner.unavailable_error(&workers)),
+                };
+            };
+
+            // could have died while idle
+            let exited = match pooled.worker.pipes_mut().try_wait().await {
+                Ok(status) => status.is_some(),
+                Err(_) => true,
+            };
+            if !exited && pooled.worker.state() == State::Ready {
+                return Ok(pooled);
+            }
+
+            self.replace(pooled);
+        }
+    }
+
+    async fn release(&self, pooled: Pooled) {
+        if let Some(pooled) = self.inner.release(pooled) {
+            stop(pooled).await;
+        }
+    }
+
+    fn replace(&self, pooled: Pooled) {
+        self.spawn(recycle(self.inner.clone(), pooled));
+    }
+
+    fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        let mut tasks = self.tasks.lock().unwrap();
+        // the finished tasks are kept by the set until joined
+        while tasks.try_join_next().is_some() {}
+        tasks.spawn(task);
+    }
+}
+
+// the exec waiting for the worker, the worker handed over to the dropped exec goes back to the pool
+struct Waiting {
+    inner: Arc<Inner>,
+    rx: oneshot::Receiver<Pooled>,
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.rx.close();
+        let Ok(pooled) = self.rx.try_recv() else {
+            return;
+        };
+
+        if let Some(pooled) = self.inner.release(pooled)
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            runtime.spawn(stop(pooled));
+        }
+    }
+}
+
+// the worker of the exec in flight, the job is cancelled when the exec is dropped
+struct Lease {
+    inner: Arc<Inner>,
+    pooled: Option<Pooled>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let Some(mut pooled) = self.pooled.take() else {
+            return;
+        };
+        // no runtime on shutdown, the worker is killed on drop
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let inner = self.inner.clone();
+        runtime.spawn(async move {
+            pooled.worker.set_cancel_timeout(inner.cancel_timeout);
+            match pooled.worker.cancel().await {
+                Ok(()) => {
+                    if let Some(pooled) = inner.release(pooled) {
+                        stop(pooled).await;
+                    }
+                }
+                Err(_) => recycle(inner, pooled).await,
+            }
+        });
+    }
+}
+
+// destroys the failed worker, the one of the current generation is replaced
+async fn recycle(inner: Arc<Inner>, mut pooled: Pooled) {
+    // reaped anyway, nothing to do with the kill error
+    _ = pooled.worker.destroy().await;
+    // the old generation is replaced by the reset
+    if inner.is_current(pooled.generation) {
+        drop(pooled);
+        allocate(inner, false).await;
+    }
+}
+
+// stopped by the pool stop or the reset, killed if it doesn't stop
+async fn stop(mut pooled: Pooled) {
+    _ = pooled.worker.stop().await;
+}
+
+// allocates the worker of the current generation, the failures are retried with the backoff
+// until the pool is stopped or reset; the initial allocations are counted for the readiness,
+// the initial one is counted as failed only while the circuit breaker is open
+async fn allocate(inner: Arc<Inner>, initial: bool) {
+    let generation = inner.workers.lock().unwrap().generation;
+    let mut failed = false;
+    let mut count_failed = |err: Option<String>| {
+        inner.progress.send_modify(|p| {
+            if initial && !failed {
+                p.failed += 1;
+                failed = true;
+            }
+            if err.is_some() {
+                p.error = err;
+            }
+        });
+    };
+
+    loop {
+        let delay = {
+            let mut workers = inner.workers.lock().unwrap();
+            match workers.crash_loop.open_until() {
+                Some(until) if until > Instant::now() => {
+                    count_failed(None);
+                    until - Instant::now()
+                }
+                _ => workers.crash_loop.delay(&inner.backoff),
+            }
+        };
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+
+        // not counted as alive while waiting out the delay
+        let Some(token) = inner.token(generation) else {
+            return;
+        };
+        if inner.workers.lock().unwrap().crash_loop.state() == BreakerState::Open {
+            continue;
+        }
+
+        match inner.allocator.allocate().await {
+            Ok(worker) => {
+                if initial {
+                    inner.progress.send_modify(|p| {
+                        p.ready += 1;
+                        // recovered after the cooldown
+                        if failed {
+                            p.failed -= 1;
+                        }
+                    });
+                }
+
+                let pooled = Pooled::new(worker, generation, token);
+                let returned = {
+                    let mut workers = inner.workers.lock().unwrap();
+                    workers.crash_loop.succeeded();
+                    workers.push(pooled)
+                };
+                if let Some(pooled) = returned {
+                    stop(pooled).await;
+                }
+                return;
+            }
+            Err(err) => {
+                drop(token);
+                let err = format!("{:#}", err);
+
+                let mut workers = inner.workers.lock().unwrap();
+                workers.crash_loop.record(&inner.breaker);
+                match workers.crash_loop.state() {
+                    // retried after the backoff, not a failure yet
+                    BreakerState::Closed | BreakerState::HalfOpen => {
+                        inner.progress.send_modify(|p| p.error = Some(err));
+                    }
+                    BreakerState::Open => count_failed(Some(err)),
+                }
+
+                // the waiting execs fail instead of waiting out the cooldown
+                if workers.unavailable() {
+                    workers.waiters.clear();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pool::Config;
+    use crate::worker::Spec;
+
+    #[test]
+    fn test_config_min_ready() {
+        let mut config = Config::new(Spec::new(&["php", "worker.php"]), 4);
+        assert_eq!(config.min_ready(), 4);
+
+        config.set_min_ready(2);
+        assert_eq!(config.min_ready(), 2);
+
+        config.set_min_ready(10);
+        assert_eq!(config.min_ready(), 4);
+    }
+}
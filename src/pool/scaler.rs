@@ -0,0 +1,95 @@
+use crate::pool::{Config, Inner, allocate, stop};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::{Instant, MissedTickBehavior, interval};
+
+// how often the queue and the idle workers are checked
+const SCALE_INTERVAL: Duration = Duration::from_millis(50);
+// window of the spawn rate limit
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Spawns the extra workers up to `max_workers` while the jobs wait longer than `queue_wait`,
+/// stops the extra workers idle for `idle_timeout`. Ends when the pool is stopped.
+pub(super) async fn scale(inner: Arc<Inner>, config: Config) {
+    let mut allocations = JoinSet::new();
+    let mut stops = JoinSet::new();
+    // spawns within the rate window
+    let mut spawned: VecDeque<Instant> = VecDeque::new();
+    let mut scaled_up: Option<Instant> = None;
+
+    let mut ticker = interval(SCALE_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        while allocations.try_join_next().is_some() {}
+        while stops.try_join_next().is_some() {}
+
+        let now = Instant::now();
+        while spawned
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= RATE_WINDOW)
+        {
+            spawned.pop_front();
+        }
+
+        let (spawn, reaped) = {
+            let mut workers = inner.workers.lock().unwrap();
+            if workers.closed {
+                return;
+            }
+
+            workers.prune_waiters();
+
+            let alive = workers.alive();
+            let waited = workers
+                .waiters
+                .oldest()
+                .map(|at| now.duration_since(at))
+                .unwrap_or_default();
+
+            let mut spawn = 0;
+            if waited > config.queue_wait {
+                spawn = config
+                    .max_workers
+                    .saturating_sub(alive)
+                    // the allocations in flight take the waiters too
+                    .min(workers.waiters.len().saturating_sub(allocations.len()))
+                    .min(config.spawn_rate.saturating_sub(spawned.len()));
+            }
+
+            // no scale down right after the scale up, the burst could come back
+            let cooled = scaled_up.is_none_or(|at| now.duration_since(at) >= config.idle_timeout);
+
+            let mut reaped = vec![];
+            if spawn == 0 && cooled && alive > config.num_workers {
+                let mut excess = alive - config.num_workers;
+                let mut i = 0;
+                while i < workers.idle.len() && excess > 0 {
+                    if now.duration_since(workers.idle[i].idle_since) >= config.idle_timeout {
+                        reaped.extend(workers.idle.remove(i));
+                        excess -= 1;
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+
+            (spawn, reaped)
+        };
+
+        if spawn > 0 {
+            scaled_up = Some(now);
+        }
+        for _ in 0..spawn {
+            spawned.push_back(now);
+            allocations.spawn(allocate(inner.clone(), false));
+        }
+
+        for pooled in reaped {
+            stops.spawn(stop(pooled));
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use tokio::time::Instant;
+
+// virtual time of the job of the tenant with the weight 1
+const COST: u64 = 1 << 20;
+
+/// Priority and tenant of the job, the default is the priority 0 and the empty tenant.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecOptions {
+    // the higher is served first
+    pub priority: i32,
+    pub tenant: String,
+}
+
+impl ExecOptions {
+    pub fn new(tenant: &str) -> Self {
+        ExecOptions {
+            priority: 0,
+            tenant: tenant.to_string(),
+        }
+    }
+
+    pub fn set_priority(&mut self, priority: i32) {
+        self.priority = priority;
+    }
+
+    pub fn set_tenant(&mut self, tenant: &str) {
+        self.tenant = tenant.to_string();
+    }
+}
+
+struct Job<T> {
+    seq: u64,
+    enqueued: Instant,
+    value: T,
+}
+
+// the first queued job of the tenant, only it is tagged, so the dropped jobs cost nothing
+struct Head {
+    priority: i32,
+    tenant: String,
+    start: u64,
+    finish: u64,
+    // of the job, the head is stale once the job is no longer the first one
+    seq: u64,
+}
+
+// the max-heap pops the highest priority, then the earliest finish tag, then the oldest
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.finish.cmp(&self.finish))
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+// queued jobs and the virtual clock of the priority level, dropped once no job is queued
+struct Level<T> {
+    now: u64,
+    // finish tag of the last served job of the tenant
+    finish: HashMap<String, u64>,
+    queues: HashMap<String, VecDeque<Job<T>>>,
+    len: usize,
+}
+
+impl<T> Default for Level<T> {
+    fn default() -> Self {
+        Level {
+            now: 0,
+            finish: HashMap::new(),
+            queues: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> Level<T> {
+    // tags the first job of the tenant
+    fn head(&self, priority: i32, tenant: &str, weight: u32) -> Option<Head> {
+        let job = self.queues.get(tenant)?.front()?;
+
+        // the idle tenant starts at the current virtual time, no credit is saved up
+        let start = match self.finish.get(tenant) {
+            Some(&finish) => finish.max(self.now),
+            None => self.now,
+        };
+
+        Some(Head {
+            priority,
+            tenant: tenant.to_string(),
+            start,
+            finish: start + COST / weight.max(1) as u64,
+            seq: job.seq,
+        })
+    }
+}
+
+/// Queue of the waiting jobs: the higher priority is served first, the tenants of the same
+/// priority share the workers in proportion to their weights, the jobs are served in the order
+/// of their virtual finish tags (weighted fair queueing), so the flood of one tenant delays
+/// the others by its weight share only. The jobs of a tenant are served in order, only the first
+/// one is tagged, so the jobs dropped while queued don't delay the rest of the tenant.
+pub(super) struct Scheduler<T> {
+    heads: BinaryHeap<Head>,
+    weights: HashMap<String, u32>,
+    levels: HashMap<i32, Level<T>>,
+    seq: u64,
+    len: usize,
+}
+
+impl<T> Scheduler<T> {
+    pub(super) fn new(weights: HashMap<String, u32>) -> Self {
+        Scheduler {
+            heads: BinaryHeap::new(),
+            weights,
+            levels: HashMap::new(),
+            seq: 0,
+            len: 0,
+        }
+    }
+
+    #[inline]
+    fn weight(&self, tenant: &str) -> u32 {
+        self.weights.get(tenant).copied().unwrap_or(1)
+    }
+
+    pub(super) fn push(&mut self, options: &ExecOptions, value: T) {
+        let weight = self.weight(&options.tenant);
+        let level = self.levels.entry(options.priority).or_default();
+        let queue = level.queues.entry(options.tenant.clone()).or_default();
+
+        self.seq += 1;
+        queue.push_back(Job {
+            seq: self.seq,
+            enqueued: Instant::now(),
+            value,
+        });
+        level.len += 1;
+        self.len += 1;
+
+        if queue.len() == 1 {
+            self.heads
+                .extend(level.head(options.priority, &options.tenant, weight));
+        }
+    }
+
+    /// Next job to serve, the jobs failing `live` (e.g. abandoned by the callers) are dropped
+    /// on the way without being charged to their tenants.
+    pub(super) fn pop(&mut self, mut live: impl FnMut(&T) -> bool) -> Option<T> {
+        while let Some(head) = self.heads.pop() {
+            let weight = self.weight(&head.tenant);
+            let Some(level) = self.levels.get_mut(&head.priority) else {
+                continue;
+            };
+            let Some(queue) = level.queues.get_mut(&head.tenant) else {
+                continue;
+            };
+            if queue.front().map(|job| job.seq) != Some(head.seq) {
+                continue;
+            }
+
+            let job = queue.pop_front().unwrap();
+            let empty = queue.is_empty();
+            level.len -= 1;
+            self.len -= 1;
+
+            let served = live(&job.value);
+            if served {
+                level.now = level.now.max(head.start);
+                level.finish.insert(head.tenant.clone(), head.finish);
+                // the tenants without the queued jobs
+                let now = level.now;
+                level.finish.retain(|_, finish| *finish > now);
+            }
+
+            match empty {
+                true => _ = level.queues.remove(&head.tenant),
+                false => self
+                    .heads
+                    .extend(level.head(head.priority, &head.tenant, weight)),
+            }
+            if level.len == 0 {
+                self.levels.remove(&head.priority);
+            }
+
+            if served {
+                return Some(job.value);
+            }
+        }
+
+        None
+    }
+
+    /// Removes the jobs, e.g. abandoned by the callers, the same as skipping them in `pop`.
+    pub(super) fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        let weights = &self.weights;
+        for (&priority, level) in &mut self.levels {
+            let mut retagged = vec![];
+            for (tenant, queue) in &mut level.queues {
+                let first = queue.front().map(|job| job.seq);
+                let before = queue.len();
+                queue.retain(|job| f(&job.value));
+
+                level.len -= before - queue.len();
+                self.len -= before - queue.len();
+                if queue.front().is_some_and(|job| Some(job.seq) != first) {
+                    retagged.push(tenant.clone());
+                }
+            }
+            level.queues.retain(|_, queue| !queue.is_empty());
+
+            // the stale heads are skipped by the pop
+            for tenant in retagged {
+                let weight = weights.get(&tenant).copied().unwrap_or(1);
+                self.heads.extend(level.head(priority, &tenant, weight));
+            }
+        }
+
+        self.levels.retain(|_, level| level.len > 0);
+    }
+
+    /// Enqueue instant of the longest waiting job.
+    pub(super) fn oldest(&self) -> Option<Instant> {
+        // the jobs of the tenant are queued in order
+        self.levels
+            .values()
+            .flat_map(|level| level.queues.values())
+            .filter_map(|queue| queue.front().map(|job| job.enqueued))
+            .min()
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.heads.clear();
+        self.levels.clear();
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pool::scheduler::{ExecOptions, Scheduler};
+    use std::collections::HashMap;
+
+    fn options(tenant: &str, priority: i32) -> ExecOptions {
+        let mut options = ExecOptions::new(tenant);
+        options.set_priority(priority);
+        options
+    }
+
+    fn drain(scheduler: &mut Scheduler<String>) -> Vec<String> {
+        std::iter::from_fn(|| scheduler.pop(|_| true)).collect()
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut scheduler = Scheduler::new(HashMap::new());
+        scheduler.push(&options("a", 0), "low 1".to_string());
+        scheduler.push(&options("a", 10), "high".to_string());
+        scheduler.push(&options("a", 0), "low 2".to_string());
+        scheduler.push(&options("a", -1), "lowest".to_string());
+        scheduler.push(&options("a", 5), "mid".to_string());
+
+        assert_eq!(scheduler.len(), 5);
+        assert_eq!(
+            drain(&mut scheduler),
+            ["high", "mid", "low 1", "low 2", "lowest"]
+        );
+    }
+
+    #[test]
+    fn test_fair_tenants() {
+        let mut scheduler = Scheduler::new(HashMap::new());
+        // the flood of the tenant a is queued first
+        for i in 0..5 {
+            scheduler.push(&options("a", 0), format!("a{}", i));
+        }
+        scheduler.push(&options("b", 0), "b0".to_string());
+        scheduler.push(&options("b", 0), "b1".to_string());
+
+        assert_eq!(
+            drain(&mut scheduler),
+            ["a0", "b0", "a1", "b1", "a2", "a3", "a4"]
+        );
+    }
+
+    #[test]
+    fn test_weighted_tenants() {
+        let weights = HashMap::from([("a".to_string(), 3), ("b".to_string(), 1)]);
+        let mut scheduler = Scheduler::new(weights);
+        for i in 0..6 {
+            scheduler.push(&options("a", 0), format!("a{}", i));
+            scheduler.push(&options("b", 0), format!("b{}", i));
+        }
+
+        let order = drain(&mut scheduler);
+        // 3 jobs of a for every job of b
+        assert_eq!(order[..8], ["a0", "a1", "a2", "b0", "a3", "a4", "a5", "b1"]);
+    }
+
+    #[test]
+    fn test_idle_tenant_no_credit() {
+        let mut scheduler = Scheduler::new(HashMap::new());
+        for i in 0..4 {
+            scheduler.push(&options("a", 0), format!("a{}", i));
+        }
+        assert_eq!(scheduler.pop(|_| true).unwrap(), "a0");
+        assert_eq!(scheduler.pop(|_| true).unwrap(), "a1");
+
+        // joins late, doesn't take over the workers for the time it was idle
+        scheduler.push(&options("b", 0), "b0".to_string());
+        scheduler.push(&options("b", 0), "b1".to_string());
+        scheduler.push(&options("b", 0), "b2".to_string());
+        assert_eq!(drain(&mut scheduler), ["b0", "a2", "b1", "a3", "b2"]);
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut scheduler = Scheduler::new(HashMap::new());
+        scheduler.push(&options("a", 0), "a0".to_string());
+        scheduler.push(&options("a", 0), "a1".to_string());
+        assert!(scheduler.oldest().is_some());
+
+        scheduler.retain(|value| value != "a0");
+        assert_eq!(drain(&mut scheduler), ["a1"]);
+        assert!(scheduler.oldest().is_none());
+    }
+
+    #[test]
+    fn test_retain_no_penalty() {
+        let mut scheduler = Scheduler::new(HashMap::new());
+        for i in 0..4 {
+            scheduler.push(&options("a", 0), format!("a{}", i));
+        }
+
+        // the callers of the first jobs gave up, the rest is served as if they were never queued
+        scheduler.retain(|value| value != "a0" && value != "a1");
+        scheduler.push(&options("b", 0), "b0".to_string());
+        scheduler.push(&options("b", 0), "b1".to_string());
+        assert_eq!(drain(&mut scheduler), ["a2", "b0", "a3", "b1"]);
+    }
+
+    #[test]
+    fn test_pop_skips_dropped() {
+        let mut scheduler = Scheduler::new(HashMap::new());
+        for i in 0..4 {
+            scheduler.push(&options("a", 0), format!("a{}", i));
+        }
+        scheduler.push(&options("b", 0), "b0".to_string());
+        scheduler.push(&options("b", 0), "b1".to_string());
+
+        // the dropped jobs are skipped without delaying the rest of the tenant
+        let live = |value: &String| value != "a0" && value != "a1";
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.pop(live)).collect();
+        assert_eq!(order, ["a2", "b0", "a3", "b1"]);
+        assert_eq!(scheduler.len(), 0);
+    }
+
+    #[test]
+    fn test_levels_dropped() {
+        let mut scheduler = Scheduler::new(HashMap::new());
+        for priority in 0..100 {
+            scheduler.push(&options("a", priority), format!("a{}", priority));
+        }
+        scheduler.retain(|value| value != "a0");
+        drain(&mut scheduler);
+
+        // no clock is kept for the caller chosen priorities
+        assert!(scheduler.levels.is_empty());
+        assert!(scheduler.heads.is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::frame::Frame;
+use crate::frame::frame_flags::{Flag, Flags};
+use crate::pipe::commands::{CancelCommand, ControlCommand};
+use crate::relay::StreamRelay;
+use std::collections::VecDeque;
+use tokio::io::{AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, sleep};
+
+// in-flight bytes per direction before the writer is blocked
+pub const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+pub type MemoryRelay = StreamRelay<ReadHalf<DuplexStream>, WriteHalf<DuplexStream>>;
+
+/// Two relays connected over the in-memory duplex, frames sent by one are received by the other.
+pub fn pair() -> (MemoryRelay, MemoryRelay) {
+    pair_with_capacity(DEFAULT_CAPACITY)
+}
+
+pub fn pair_with_capacity(capacity: usize) -> (MemoryRelay, MemoryRelay) {
+    let (a, b) = tokio::io::duplex(capacity);
+    let (a_rx, a_tx) = tokio::io::split(a);
+    let (b_rx, b_tx) = tokio::io::split(b);
+
+    (StreamRelay::new(a_rx, a_tx), StreamRelay::new(b_rx, b_tx))
+}
+
+/// How the fake worker responds to a job.
+#[derive(Debug, Clone)]
+pub enum Behavior {
+    // respond with the same flags, options and payload
+    Echo,
+    // respond with the ERROR flag and the message as payload
+    Error(String),
+    // sleep, then echo
+    Delay(Duration),
+    // write raw bytes instead of the frame, like PHP warnings printed to STDOUT
+    Garbage(Vec<u8>),
+    // close the relay without responding
+    Crash,
+}
+
+/// Worker side of the memory relay, follows the worker protocol (PID handshake, stop command)
+/// and responds to the jobs according to the script. Jobs past the end of the script are echoed.
+pub struct FakeWorker {
+    relay: MemoryRelay,
+    pid: u32,
+    script: VecDeque<Behavior>,
+}
+
+impl FakeWorker {
+    pub fn new(relay: MemoryRelay, script: Vec<Behavior>) -> Self {
+        FakeWorker {
+            relay,
+            pid: std::process::id(),
+            script: script.into(),
+        }
+    }
+
+    pub fn set_pid(&mut self, pid: u32) {
+        self.pid = pid;
+    }
+
+    pub fn spawn(self) -> JoinHandle<anyhow::Result<()>> {
+        tokio::spawn(self.serve())
+    }
+
+    /// Serves the jobs until the stop command, crash or the relay is closed by the parent.
+    pub async fn serve(mut self) -> anyhow::Result<()> {
+        loop {
+            let frame = match self.relay.receive().await {
+                Ok(frame) => frame,
+                // parent is gone
+                Err(_) => return Ok(()),
+            };
+
+            if frame.read_flags().contains(Flags::CONTROL) {
+                match ControlCommand::parse(&frame)? {
+                    ControlCommand::Pid(request) => {
+                        self.relay.respond_pid(&request, self.pid).await?
+                    }
+                    ControlCommand::Stop => return Ok(()),
+                    // the jobs are served one by one, nothing is in flight here
+                    ControlCommand::Cancel => {
+                        self.relay.send_control(CancelCommand::default()).await?
+                    }
+                }
+
+                continue;
+            }
+
+            match self.script.pop_front().unwrap_or(Behavior::Echo) {
+                Behavior::Echo => self.echo(&frame).await?,
+                Behavior::Error(msg) => {
+                    let mut response = Frame::default();
+                    response.write_version(self.relay.version());
+                    response.write_flags(&[Flag::Error]);
+                    response.write_payload(msg.as_bytes());
+                    response.write_crc();
+                    self.relay.send(&mut response).await?;
+                }
+                Behavior::Delay(delay) => {
+                    sleep(delay).await;
+                    self.echo(&frame).await?;
+                }
+                Behavior::Garbage(data) => {
+                    self.relay.writer_mut().write_all(&data).await?;
+                    self.relay.writer_mut().flush().await?;
+                }
+                Behavior::Crash => return Ok(()),
+            }
+        }
+    }
+
+    async fn echo(&mut self, frame: &Frame) -> anyhow::Result<()> {
+        let mut response = Frame::default();
+        response.write_version(self.relay.version());
+        response.set_flags(frame.read_flags());
+        if let Some(options) = frame.read_options() {
+            response.write_options(&options)?;
+        }
+        response.write_payload(frame.payload());
+        response.write_crc();
+
+        self.relay.send(&mut response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::Frame;
+    use crate::frame::frame_flags::{Flag, Flags};
+    use crate::frame::integrity::Integrity;
+    use crate::relay::memory::{Behavior, FakeWorker, MemoryRelay, pair};
+    use tokio::time::{Duration, Instant};
+
+    fn frame(payload: &[u8]) -> Frame {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_flags(&[Flag::CodecRaw]);
+        ff.write_options(&[3]).unwrap();
+        ff.write_payload(payload);
+        ff.write_crc();
+        ff
+    }
+
+    async fn exec(relay: &mut MemoryRelay, payload: &[u8]) -> anyhow::Result<Frame> {
+        relay.send(&mut frame(payload)).await?;
+        relay.receive().await
+    }
+
+    #[tokio::test]
+    async fn test_pair() {
+        let (mut a, mut b) = pair();
+
+        a.send(&mut frame(b"hello")).await.unwrap();
+        let res = b.receive().await.unwrap();
+        assert_eq!(res.payload(), b"hello");
+        assert_eq!(res.read_options().unwrap(), vec![3]);
+
+        b.send(&mut frame(b"world")).await.unwrap();
+        assert_eq!(a.receive().await.unwrap().payload(), b"world");
+    }
+
+    #[tokio::test]
+    async fn test_fake_worker_pid() {
+        let (mut parent, child) = pair();
+        let mut worker = FakeWorker::new(child, vec![]);
+        worker.set_pid(42);
+        let handle = worker.spawn();
+
+        assert_eq!(parent.send_pid().await.unwrap(), 42);
+        assert_eq!(parent.version(), 1);
+
+        let res = exec(&mut parent, b"hello").await.unwrap();
+        assert_eq!(res.payload(), b"hello");
+        assert_eq!(res.read_flags(), Flags::CODEC_RAW);
+
+        drop(parent);
+        handle.await.unwrap().unwrap();
+    }
+
+    // paused clock skips the garbage read timeout
+    #[tokio::test(start_paused = true)]
+    async fn test_fake_worker_script() {
+        let (mut parent, child) = pair();
+        FakeWorker::new(
+            child,
+            vec![
+                Behavior::Error("boom".to_string()),
+                Behavior::Delay(Duration::from_millis(50)),
+                Behavior::Garbage(b"warning: some weird php error, THIS IS PHP".to_vec()),
+            ],
+        )
+        .spawn();
+
+        let res = exec(&mut parent, b"hello").await.unwrap();
+        assert!(res.read_flags().contains(Flags::ERROR));
+        assert_eq!(res.payload(), b"boom");
+
+        let start = Instant::now();
+        let res = exec(&mut parent, b"hello").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(res.payload(), b"hello");
+
+        let err = exec(&mut parent, b"hello").await.unwrap_err();
+        assert!(err.to_string().starts_with("validation failed"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fake_worker_cancel() {
+        let (mut parent, child) = pair();
+        FakeWorker::new(child, vec![Behavior::Delay(Duration::from_millis(50))]).spawn();
+
+        // the exec is dropped before the response
+        let mut job = frame(b"hello");
+        let res = tokio::time::timeout(Duration::from_millis(10), parent.exec(&mut job)).await;
+        assert!(res.is_err());
+        assert!(!parent.write_interrupted());
+
+        // the response of the finished job is discarded with the acknowledgement
+        assert_eq!(parent.cancel().await.unwrap(), 1);
+        assert_eq!(
+            exec(&mut parent, b"world").await.unwrap().payload(),
+            b"world"
+        );
+
+        // nothing in flight
+        assert_eq!(parent.cancel().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fake_worker_crash() {
+        let (mut parent, child) = pair();
+        let handle = FakeWorker::new(child, vec![Behavior::Echo, Behavior::Crash]).spawn();
+
+        assert!(exec(&mut parent, b"hello").await.is_ok());
+        assert!(exec(&mut parent, b"hello").await.is_err());
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fake_worker_payload_crc() {
+        let (mut parent, mut child) = pair();
+        parent.set_integrity(Integrity::HeaderPayload);
+        child.set_integrity(Integrity::HeaderPayload);
+        FakeWorker::new(child, vec![]).spawn();
+
+        parent.send_pid().await.unwrap();
+        let res = exec(&mut parent, b"hello").await.unwrap();
+        assert_eq!(res.payload(), b"hello");
+        assert_eq!(res.read_options().unwrap(), vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_resend_payload_crc() {
+        let (mut a, mut b) = pair();
+        a.set_integrity(Integrity::HeaderPayload);
+        b.set_integrity(Integrity::HeaderPayload);
+
+        // a retry sends the same frame again
+        let mut ff = frame(b"hello");
+        a.send(&mut ff).await.unwrap();
+        a.send(&mut ff).await.unwrap();
+        assert_eq!(ff.read_options().unwrap(), vec![3]);
+
+        for _ in 0..2 {
+            let res = b.receive().await.unwrap();
+            assert_eq!(res.payload(), b"hello");
+            assert_eq!(res.read_options().unwrap(), vec![3]);
+        }
+    }
+}
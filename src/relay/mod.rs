@@ -0,0 +1,219 @@
+pub mod memory;
+mod reader;
+
+use crate::buffer::BufferPool;
+use crate::frame::Frame;
+use crate::frame::frame_flags::Flag::{CodecJSON, Control};
+use crate::frame::frame_flags::Flags;
+use crate::frame::integrity::Integrity;
+use crate::frame::version::{SUPPORTED_VERSIONS, VERSION_1, negotiate};
+use crate::metrics::{self, RelayMetrics};
+use crate::pipe::commands::{CancelCommand, ControlCommand, PidCommand};
+use crate::trace::record;
+use anyhow::anyhow;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+pub use reader::FrameReader;
+
+pub trait Marshaller {
+    fn marshal(&mut self) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Goridge relay over a pair of async streams (child stdio, socket, in-memory duplex).
+pub struct StreamRelay<R, W> {
+    reader: FrameReader<R>,
+    writer: W,
+    // protocol version negotiated during the PID handshake
+    version: u8,
+    integrity: Integrity,
+    // `relay` label of the metrics
+    name: Arc<str>,
+    metrics: RelayMetrics,
+    // the write future was dropped in the middle of the frame
+    write_interrupted: bool,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> StreamRelay<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        StreamRelay {
+            reader: FrameReader::new(reader),
+            writer,
+            version: VERSION_1,
+            integrity: Integrity::default(),
+            name: Arc::from(metrics::DEFAULT_RELAY),
+            metrics: RelayMetrics::new(metrics::DEFAULT_RELAY),
+            write_interrupted: false,
+        }
+    }
+
+    /// Received frames draw their buffers from the pool and return them on drop.
+    pub fn set_buffer_pool(&mut self, pool: Arc<BufferPool>) {
+        self.reader.set_buffer_pool(pool);
+    }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Both sides of the relay should use the same integrity mode.
+    pub fn set_integrity(&mut self, integrity: Integrity) {
+        self.integrity = integrity;
+        self.reader.set_integrity(integrity);
+    }
+
+    #[inline]
+    pub fn integrity(&self) -> Integrity {
+        self.integrity
+    }
+
+    /// Name of the relay, the `relay` label of the metrics.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Arc::from(name);
+        self.metrics = RelayMetrics::new(name);
+        self.reader.set_name(self.name.clone());
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn reader_mut(&mut self) -> &mut FrameReader<R> {
+        &mut self.reader
+    }
+
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// The send was dropped in the middle of the frame, the peer is left with the partial frame
+    /// and the stream can't be used anymore.
+    #[inline]
+    pub fn write_interrupted(&self) -> bool {
+        self.write_interrupted
+    }
+
+    /// The receive was dropped in the middle of the frame, see `FrameReader::read_interrupted`.
+    #[inline]
+    pub fn read_interrupted(&self) -> bool {
+        self.reader.read_interrupted()
+    }
+
+    pub async fn send(&mut self, frame: &mut Frame) -> anyhow::Result<()> {
+        self.write_frame(frame).await
+    }
+
+    async fn write_frame(&mut self, frame: &mut Frame) -> anyhow::Result<()> {
+        record!("flags", tracing::field::debug(frame.read_flags()));
+        record!("payload_len", frame.read_payload_len());
+        record!(
+            "crc",
+            match frame.verify_crc() {
+                Ok(_) => "valid",
+                Err(_) => "invalid",
+            }
+        );
+
+        // the payload CRC goes to the outgoing bytes only, the frame could be sent again
+        let data = match self.integrity.verify_payload() {
+            true => frame.bytes_with_payload_crc()?,
+            false => frame.bytes(),
+        };
+        self.write_interrupted = true;
+        self.writer.write_all(&data).await?;
+        self.writer.flush().await?;
+        self.write_interrupted = false;
+
+        self.metrics.frame_sent(data.len());
+        Ok(())
+    }
+
+    pub async fn receive(&mut self) -> anyhow::Result<Frame> {
+        self.reader.read_frame().await
+    }
+
+    /// Sends the request and waits for the response.
+    pub async fn exec(&mut self, frame: &mut Frame) -> anyhow::Result<Frame> {
+        let start = Instant::now();
+        self.send(frame).await?;
+        let response = self.receive().await?;
+
+        self.metrics.exec_duration(start.elapsed());
+        Ok(response)
+    }
+
+    pub async fn send_control<T: Marshaller>(&mut self, mut payload: T) -> anyhow::Result<()> {
+        let mut frame = Frame::default();
+
+        frame.write_version(self.version);
+        frame.write_flags(&[Control, CodecJSON]);
+
+        let data = payload.marshal()?;
+        // we don't need to Borrow the data here
+        frame.write_payload(&data);
+        frame.write_crc();
+
+        self.write_frame(&mut frame).await
+    }
+
+    /// Parent side of the PID handshake, returns the worker PID.
+    pub async fn send_pid(&mut self) -> anyhow::Result<u32> {
+        self.send_control(PidCommand::default()).await?;
+
+        let f = self.receive().await?;
+
+        if !f.read_flags().contains(Flags::CONTROL) {
+            return Err(anyhow!(
+                "unexpected response, header is missing, no CONTROL flag"
+            ));
+        }
+
+        let payload = f.payload();
+        let res: PidCommand = serde_json::from_slice(payload)?;
+
+        if res.pid == 0 {
+            return Err(anyhow!("pid should be greater than 0"));
+        }
+
+        // legacy workers don't send the versions and speak only the first version
+        self.version = negotiate(SUPPORTED_VERSIONS, &res.versions)?;
+
+        Ok(res.pid)
+    }
+
+    /// Parent side of the cancellation: asks the worker to abort the job in flight and discards
+    /// the frames until the acknowledgement, e.g. the response of the job finished meanwhile.
+    /// Returns the number of the discarded frames. Fails once the worker not knowing the command
+    /// exits, see `CancelCommand`.
+    pub async fn cancel(&mut self) -> anyhow::Result<usize> {
+        self.send_control(CancelCommand::default()).await?;
+
+        let mut discarded = 0;
+        loop {
+            let frame = self.receive().await?;
+            if frame.read_flags().contains(Flags::CONTROL)
+                && matches!(ControlCommand::parse(&frame), Ok(ControlCommand::Cancel))
+            {
+                return Ok(discarded);
+            }
+            discarded += 1;
+        }
+    }
+
+    /// Worker side of the PID handshake, replies to the parent with the worker `pid`.
+    pub async fn respond_pid(&mut self, request: &PidCommand, pid: u32) -> anyhow::Result<()> {
+        let version = negotiate(SUPPORTED_VERSIONS, &request.versions)?;
+
+        self.send_control(PidCommand {
+            pid,
+            versions: SUPPORTED_VERSIONS.to_vec(),
+        })
+        .await?;
+
+        self.version = version;
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use crate::buffer::BufferPool;
+use crate::frame::header::MAX_HL;
+use crate::frame::integrity::Integrity;
+use crate::frame::version::{SUPPORTED_VERSIONS, VERSION_1};
+use crate::frame::{Frame, WORD};
+use crate::metrics::{self, DecodeError, RelayMetrics};
+use crate::trace::record;
+use anyhow::anyhow;
+use std::str::from_utf8;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::time::{Duration, timeout};
+
+/// Streaming frames decoder over any async reader (child stdout, socket, in-memory duplex).
+/// Holds the buffered reader between the frames, so the bytes of the next frame are not lost.
+pub struct FrameReader<R> {
+    reader: BufReader<R>,
+    buffer_pool: Option<Arc<BufferPool>>,
+    integrity: Integrity,
+    max_payload_len: u32,
+    // `relay` label of the metrics
+    name: Arc<str>,
+    metrics: RelayMetrics,
+    // the read future was dropped in the middle of the frame
+    read_interrupted: bool,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(reader: R) -> Self {
+        FrameReader {
+            reader: BufReader::new(reader),
+            buffer_pool: None,
+            integrity: Integrity::default(),
+            max_payload_len: u32::MAX,
+            name: Arc::from(metrics::DEFAULT_RELAY),
+            metrics: RelayMetrics::new(metrics::DEFAULT_RELAY),
+            read_interrupted: false,
+        }
+    }
+
+    pub fn set_buffer_pool(&mut self, pool: Arc<BufferPool>) {
+        self.buffer_pool = Some(pool);
+    }
+
+    pub fn set_integrity(&mut self, integrity: Integrity) {
+        self.integrity = integrity;
+    }
+
+    #[inline]
+    pub fn integrity(&self) -> Integrity {
+        self.integrity
+    }
+
+    /// Frames with the bigger payload are rejected before the payload is allocated.
+    pub fn set_max_payload_len(&mut self, len: u32) {
+        self.max_payload_len = len;
+    }
+
+    pub fn set_name(&mut self, name: Arc<str>) {
+        self.metrics = RelayMetrics::new(&name);
+        self.name = name;
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader.get_mut()
+    }
+
+    /// The read was dropped in the middle of the frame, its bytes are lost
+    /// and the stream can't be used anymore.
+    #[inline]
+    pub fn read_interrupted(&self) -> bool {
+        self.read_interrupted
+    }
+
+    pub async fn read_frame(&mut self) -> anyhow::Result<Frame> {
+        // waiting for the frame consumes nothing, the drop is safe up to the first byte
+        self.reader.fill_buf().await?;
+        self.read_interrupted = true;
+
+        // io error unless the decoder says otherwise
+        let mut kind = DecodeError::Io;
+        let res = self.decode(&mut kind).await;
+        // the failed stream is misaligned anyway
+        self.read_interrupted = res.is_err();
+
+        match &res {
+            Ok(fr) => self
+                .metrics
+                .frame_received(fr.header().len() + fr.payload().len()),
+            Err(_) => metrics::decode_error(&self.name, kind),
+        }
+
+        res
+    }
+
+    async fn decode(&mut self, kind: &mut DecodeError) -> anyhow::Result<Frame> {
+        let mut fr = match &self.buffer_pool {
+            Some(pool) => Frame::with_pool(pool.clone()),
+            None => Frame::default(),
+        };
+
+        // read-only header, 12 bytes
+        self.reader.read_exact(fr.header_mut()).await?;
+
+        // CRC covers the first 6 bytes only, so the garbage is detected before reading the options,
+        // otherwise the header len from the garbage could make us wait for the bytes which never come
+        if self.integrity.verify_header() && fr.verify_crc().is_err() {
+            record!("crc", "invalid");
+            *kind = DecodeError::HeaderCrc;
+
+            let mut buffer = vec![];
+            let timeout_dur = Duration::from_secs(2);
+            _ = timeout(timeout_dur, self.reader.read_to_end(&mut buffer)).await;
+
+            // TODO: handle error match here!
+            let msg = match from_utf8(fr.header()) {
+                Ok(m) => String::from(m),
+                Err(_) => String::new(),
+            };
+
+            let bufmsg = match from_utf8(&buffer) {
+                Ok(m) => String::from(m),
+                Err(_) => String::new(),
+            };
+
+            return Err(anyhow!(
+                "validation failed on the message sent to STDOUT, cause {}{}",
+                msg,
+                bufmsg
+            ));
+        }
+
+        if fr.read_hl() > MAX_HL {
+            *kind = DecodeError::HeaderLen;
+            return Err(anyhow!(
+                "options size is limited by 40 bytes (10 4-bytes words), header len is {}",
+                fr.read_hl()
+            ));
+        }
+
+        // we have an option
+        if fr.read_hl() > 3 {
+            let opts_len = (fr.read_hl() - 3) * WORD;
+            let mut tmp = vec![0; opts_len as usize];
+            self.reader.read_exact(&mut tmp).await?;
+
+            fr.extend_header(&tmp);
+        }
+
+        record!("flags", tracing::field::debug(fr.read_flags()));
+        record!("payload_len", fr.read_payload_len());
+
+        // the layout after the fixed header depends on the protocol version
+        match fr.version() {
+            VERSION_1 => self.decode_v1(fr, kind).await,
+            version => {
+                *kind = DecodeError::Version;
+                Err(anyhow!(
+                    "unsupported protocol version {}, supported versions: {:?}",
+                    version,
+                    SUPPORTED_VERSIONS
+                ))
+            }
+        }
+    }
+
+    // payload bounded by the payload len, optionally followed by the payload CRC option
+    async fn decode_v1(&mut self, mut fr: Frame, kind: &mut DecodeError) -> anyhow::Result<Frame> {
+        let pld_len = fr.read_payload_len();
+        if pld_len > self.max_payload_len {
+            *kind = DecodeError::PayloadTooBig;
+            return Err(anyhow!(
+                "payload is too big: {} bytes, limit is {} bytes",
+                pld_len,
+                self.max_payload_len
+            ));
+        }
+
+        if pld_len > 0 {
+            self.reader
+                .read_exact(fr.init_payload_mut(pld_len as usize))
+                .await?;
+        }
+
+        if self.integrity.verify_payload() {
+            *kind = DecodeError::PayloadCrc;
+            fr.verify_payload_crc().inspect_err(|_| {
+                record!("crc", "payload invalid");
+            })?;
+            fr.strip_payload_crc()?;
+        }
+
+        record!(
+            "crc",
+            match self.integrity {
+                Integrity::None => "unchecked",
+                _ => "valid",
+            }
+        );
+
+        Ok(fr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::Frame;
+    use crate::frame::frame_flags::Flag;
+    use crate::relay::reader::FrameReader;
+    use tokio::io::AsyncWriteExt;
+
+    fn frame(payload: &[u8]) -> Frame {
+        let mut ff = Frame::default();
+        ff.write_version(1);
+        ff.write_flags(&[Flag::CodecRaw]);
+        ff.write_options(&[7]).unwrap();
+        ff.write_payload(payload);
+        ff.write_crc();
+        ff
+    }
+
+    #[tokio::test]
+    async fn test_read_back_to_back_frames() {
+        let (mut tx, rx) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(rx);
+
+        let mut data = frame(b"hello").bytes();
+        data.extend(frame(b"world").bytes());
+        tx.write_all(&data).await.unwrap();
+
+        assert_eq!(reader.read_frame().await.unwrap().payload(), b"hello");
+        let res = reader.read_frame().await.unwrap();
+        assert_eq!(res.payload(), b"world");
+        assert_eq!(res.read_options().unwrap(), vec![7]);
+    }
+
+    #[tokio::test]
+    async fn test_read_default_version() {
+        let (mut tx, rx) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(rx);
+
+        // the version is not written explicitly
+        let mut ff = Frame::default();
+        ff.write_payload(b"hello");
+        ff.write_crc();
+        tx.write_all(&ff.bytes()).await.unwrap();
+
+        let res = reader.read_frame().await.unwrap();
+        assert_eq!(res.version(), 1);
+        assert_eq!(res.payload(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_read_unsupported_version() {
+        let (mut tx, rx) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(rx);
+
+        let mut ff = frame(b"hello");
+        ff.write_version(2);
+        ff.write_crc();
+        tx.write_all(&ff.bytes()).await.unwrap();
+
+        let err = reader.read_frame().await.unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("unsupported protocol version 2")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_garbage() {
+        let (mut tx, rx) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(rx);
+
+        tx.write_all(b"warning: some weird php error, THIS IS PHP")
+            .await
+            .unwrap();
+        drop(tx);
+
+        let err = reader.read_frame().await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "validation failed on the message sent to STDOUT, cause warning: some weird php error, THIS IS PHP"
+        );
+    }
+
+    // paused clock skips the garbage read timeout
+    #[tokio::test(start_paused = true)]
+    async fn test_read_garbage_with_big_header_len() {
+        let (mut tx, rx) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(rx);
+
+        // header len nibble is 15, options are never sent and the writer is kept open
+        tx.write_all(&[0xFF; 12]).await.unwrap();
+
+        assert!(reader.read_frame().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_truncated() {
+        let (mut tx, rx) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(rx);
+
+        let data = frame(b"hello").bytes();
+        tx.write_all(&data[..data.len() - 1]).await.unwrap();
+        drop(tx);
+
+        assert!(reader.read_frame().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_payload_limit() {
+        let (mut tx, rx) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(rx);
+        reader.set_max_payload_len(4);
+
+        tx.write_all(&frame(b"hello").bytes()).await.unwrap();
+
+        assert!(reader.read_frame().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_interrupted() {
+        let (mut tx, rx) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(rx);
+        let timeout = std::time::Duration::from_millis(10);
+
+        // nothing is lost while waiting for the frame
+        assert!(
+            tokio::time::timeout(timeout, reader.read_frame())
+                .await
+                .is_err()
+        );
+        assert!(!reader.read_interrupted());
+
+        let data = frame(b"hello").bytes();
+        tx.write_all(&data[..14]).await.unwrap();
+        assert!(
+            tokio::time::timeout(timeout, reader.read_frame())
+                .await
+                .is_err()
+        );
+        assert!(reader.read_interrupted());
+    }
+}
@@ -0,0 +1,435 @@
+use crate::capture::{CaptureReader, Direction, MAGIC, Record};
+use crate::frame::Frame;
+use crate::frame::frame_flags::Flags;
+use crate::pipe::Pipes;
+use crate::pipe::commands::ControlCommand;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Recorded request with the response the worker sent back.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub request: Frame,
+    pub response: Option<Frame>,
+    // time between the request and the response in the capture
+    pub recorded_latency: Option<Duration>,
+}
+
+// request waiting for the answer, the worker answers in order
+enum Pending {
+    Pid,
+    Cancel,
+    Job(usize),
+}
+
+/// Pairs the requests with the responses, control frames and garbage are skipped.
+/// The job cancelled before its response has no recorded response.
+pub fn exchanges<I: IntoIterator<Item = Record>>(records: I) -> Vec<Exchange> {
+    let mut exchanges: Vec<Exchange> = vec![];
+    let mut pending: VecDeque<(Pending, Record)> = VecDeque::new();
+
+    for record in records {
+        let Some(frame) = record.frame() else {
+            continue;
+        };
+        let control = match frame.read_flags().contains(Flags::CONTROL) {
+            true => ControlCommand::parse(&frame).ok(),
+            false => None,
+        };
+
+        match (record.direction, control) {
+            // only the PID and the cancel commands are answered
+            (Direction::ToWorker, Some(ControlCommand::Pid(_))) => {
+                pending.push_back((Pending::Pid, record));
+            }
+            (Direction::ToWorker, Some(ControlCommand::Cancel)) => {
+                pending.push_back((Pending::Cancel, record));
+            }
+            // the stop command is not answered
+            (Direction::ToWorker, _) if frame.read_flags().contains(Flags::CONTROL) => {}
+            (Direction::ToWorker, _) => {
+                pending.push_back((Pending::Job(exchanges.len()), record));
+                exchanges.push(Exchange {
+                    request: frame,
+                    response: None,
+                    recorded_latency: None,
+                });
+            }
+            // the cancelled job got no response, its response is not waited for anymore
+            (Direction::FromWorker, Some(ControlCommand::Cancel)) => {
+                while let Some((request, _)) = pending.pop_front() {
+                    if matches!(request, Pending::Cancel) {
+                        break;
+                    }
+                }
+            }
+            (Direction::FromWorker, _) => {
+                if let Some((Pending::Job(idx), request)) = pending.pop_front() {
+                    exchanges[idx].recorded_latency =
+                        record.timestamp.duration_since(request.timestamp).ok();
+                    exchanges[idx].response = Some(frame);
+                }
+            }
+        }
+    }
+
+    exchanges
+}
+
+/// Loads the exchanges from the goridge-tap capture or from the concatenated request frames
+/// (`Frame::bytes()`), the latter has no recorded responses.
+pub fn load(data: &[u8]) -> anyhow::Result<Vec<Exchange>> {
+    if data.starts_with(MAGIC) {
+        let records = CaptureReader::new(data)?.collect::<anyhow::Result<Vec<_>>>()?;
+        return Ok(exchanges(records));
+    }
+
+    let mut exchanges = vec![];
+    let mut offset = 0;
+    while offset < data.len() {
+        match Frame::decode(&data[offset..])? {
+            Some((request, n)) => {
+                request.verify_crc()?;
+                offset += n;
+
+                if !request.read_flags().contains(Flags::CONTROL) {
+                    exchanges.push(Exchange {
+                        request,
+                        response: None,
+                        recorded_latency: None,
+                    });
+                }
+            }
+            None => anyhow::bail!("truncated frame at offset {}", offset),
+        }
+    }
+
+    Ok(exchanges)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    Flags {
+        expected: Flags,
+        actual: Flags,
+    },
+    Options {
+        expected: Vec<u32>,
+        actual: Vec<u32>,
+    },
+    Payload {
+        // first differing byte
+        offset: usize,
+        expected_len: usize,
+        actual_len: usize,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Flags { expected, actual } => {
+                write!(f, "flags: expected {:?}, got {:?}", expected, actual)
+            }
+            Difference::Options { expected, actual } => {
+                write!(f, "options: expected {:?}, got {:?}", expected, actual)
+            }
+            Difference::Payload {
+                offset,
+                expected_len,
+                actual_len,
+            } => write!(
+                f,
+                "payload differs at byte {}: expected {} bytes, got {} bytes",
+                offset, expected_len, actual_len
+            ),
+        }
+    }
+}
+
+pub fn diff(expected: &Frame, actual: &Frame) -> Vec<Difference> {
+    let mut differences = vec![];
+
+    if expected.read_flags() != actual.read_flags() {
+        differences.push(Difference::Flags {
+            expected: expected.read_flags(),
+            actual: actual.read_flags(),
+        });
+    }
+
+    let expected_options: Vec<u32> = expected.options().collect();
+    let actual_options: Vec<u32> = actual.options().collect();
+    if expected_options != actual_options {
+        differences.push(Difference::Options {
+            expected: expected_options,
+            actual: actual_options,
+        });
+    }
+
+    let (expected, actual) = (expected.payload(), actual.payload());
+    if expected != actual {
+        let offset = expected
+            .iter()
+            .zip(actual)
+            .position(|(e, a)| e != a)
+            .unwrap_or(expected.len().min(actual.len()));
+
+        differences.push(Difference::Payload {
+            offset,
+            expected_len: expected.len(),
+            actual_len: actual.len(),
+        });
+    }
+
+    differences
+}
+
+#[derive(Debug)]
+pub struct ExchangeResult {
+    pub latency: Duration,
+    pub recorded_latency: Option<Duration>,
+    pub response: Frame,
+    // empty when the response matches the recorded one or nothing was recorded
+    pub differences: Vec<Difference>,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub results: Vec<ExchangeResult>,
+}
+
+impl Report {
+    /// Number of the responses not matching the recorded ones.
+    pub fn mismatches(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| !r.differences.is_empty())
+            .count()
+    }
+
+    /// Replay latency percentile, `p` in 0.0..=1.0.
+    pub fn latency_percentile(&self, p: f64) -> Option<Duration> {
+        let mut latencies: Vec<Duration> = self.results.iter().map(|r| r.latency).collect();
+        percentile(&mut latencies, p)
+    }
+
+    /// Recorded latency percentile, `p` in 0.0..=1.0.
+    pub fn recorded_latency_percentile(&self, p: f64) -> Option<Duration> {
+        let mut latencies: Vec<Duration> = self
+            .results
+            .iter()
+            .filter_map(|r| r.recorded_latency)
+            .collect();
+        percentile(&mut latencies, p)
+    }
+}
+
+pub fn percentile(latencies: &mut [Duration], p: f64) -> Option<Duration> {
+    if latencies.is_empty() {
+        return None;
+    }
+
+    latencies.sort_unstable();
+    let idx = ((latencies.len() - 1) as f64 * p.clamp(0.0, 1.0)).round() as usize;
+    Some(latencies[idx])
+}
+
+/// Sends the requests one by one and compares the responses with the recorded ones.
+/// PID handshake is expected to be done by the caller.
+pub async fn replay(pipes: &mut Pipes, exchanges: &[Exchange]) -> anyhow::Result<Report> {
+    let mut report = Report::default();
+
+    for (idx, exchange) in exchanges.iter().enumerate() {
+        let mut request = exchange.request.clone();
+
+        let start = Instant::now();
+        pipes.send(&mut request).await?;
+        let response = match pipes.receive_stdout().await {
+            Ok(response) => response,
+            Err(err) => anyhow::bail!("no response for the exchange #{}: {}", idx, err),
+        };
+        let latency = start.elapsed();
+
+        let differences = match &exchange.response {
+            Some(expected) => diff(expected, &response),
+            None => vec![],
+        };
+
+        report.results.push(ExchangeResult {
+            latency,
+            recorded_latency: exchange.recorded_latency,
+            response,
+            differences,
+        });
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capture::{CaptureWriter, Direction, Record};
+    use crate::frame::Frame;
+    use crate::frame::frame_flags::{Flag, Flags};
+    use crate::pipe::commands::{CancelCommand, PidCommand, StopCommand};
+    use crate::relay::Marshaller;
+    use crate::replay::{Difference, diff, exchanges, load, percentile};
+    use std::time::Duration;
+
+    fn frame(flags: &[Flag], payload: &[u8]) -> Frame {
+        let mut frame = Frame::default();
+        frame.write_version(1);
+        frame.write_flags(flags);
+        frame.write_payload(payload);
+        frame.write_crc();
+        frame
+    }
+
+    fn record(direction: Direction, mut frame: Frame) -> Record {
+        Record::new(direction, frame.bytes())
+    }
+
+    #[test]
+    fn test_exchanges_skip_control() {
+        let pid = frame(
+            &[Flag::Control, Flag::CodecJSON],
+            &PidCommand::default().marshal().unwrap(),
+        );
+        let stop = frame(
+            &[Flag::Control, Flag::CodecJSON],
+            &StopCommand::default().marshal().unwrap(),
+        );
+
+        let records = vec![
+            record(Direction::ToWorker, pid.clone()),
+            record(Direction::FromWorker, pid),
+            record(Direction::ToWorker, frame(&[Flag::CodecRaw], b"hello")),
+            Record::new(Direction::FromWorker, b"warning: some garbage".to_vec()),
+            record(Direction::FromWorker, frame(&[Flag::CodecRaw], b"world")),
+            record(
+                Direction::ToWorker,
+                frame(&[Flag::CodecRaw], b"no response"),
+            ),
+            record(Direction::ToWorker, stop),
+        ];
+
+        let res = exchanges(records);
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].request.payload(), b"hello");
+        assert_eq!(res[0].response.as_ref().unwrap().payload(), b"world");
+        assert!(res[0].recorded_latency.is_some());
+        assert_eq!(res[1].request.payload(), b"no response");
+        assert!(res[1].response.is_none());
+    }
+
+    #[test]
+    fn test_exchanges_cancel() {
+        let cancel = frame(
+            &[Flag::Control, Flag::CodecJSON],
+            &CancelCommand::default().marshal().unwrap(),
+        );
+
+        let records = vec![
+            // cancelled before the response
+            record(Direction::ToWorker, frame(&[Flag::CodecRaw], b"dropped")),
+            record(Direction::ToWorker, cancel.clone()),
+            record(Direction::FromWorker, cancel.clone()),
+            // the response came before the cancel
+            record(Direction::ToWorker, frame(&[Flag::CodecRaw], b"finished")),
+            record(Direction::ToWorker, cancel.clone()),
+            record(Direction::FromWorker, frame(&[Flag::CodecRaw], b"finished")),
+            record(Direction::FromWorker, cancel),
+            record(Direction::ToWorker, frame(&[Flag::CodecRaw], b"hello")),
+            record(Direction::FromWorker, frame(&[Flag::CodecRaw], b"hello")),
+        ];
+
+        let res = exchanges(records);
+        assert_eq!(res.len(), 3);
+        assert!(res[0].response.is_none());
+        assert_eq!(res[1].response.as_ref().unwrap().payload(), b"finished");
+        assert_eq!(res[2].request.payload(), b"hello");
+        assert_eq!(res[2].response.as_ref().unwrap().payload(), b"hello");
+    }
+
+    #[test]
+    fn test_load() {
+        let mut requests = frame(&[Flag::CodecRaw], b"hello").bytes();
+        requests.extend(frame(&[Flag::CodecRaw], b"world").bytes());
+
+        let res = load(&requests).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[1].request.payload(), b"world");
+        assert!(load(&requests[..requests.len() - 1]).is_err());
+
+        let mut capture = vec![];
+        let mut writer = CaptureWriter::new(&mut capture).unwrap();
+        let hello = frame(&[Flag::CodecRaw], b"hello");
+        writer
+            .write_record(&record(Direction::ToWorker, hello.clone()))
+            .unwrap();
+        writer
+            .write_record(&record(Direction::FromWorker, hello))
+            .unwrap();
+
+        let res = load(&capture).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].response.as_ref().unwrap().payload(), b"hello");
+    }
+
+    #[test]
+    fn test_diff() {
+        let expected = frame(&[Flag::CodecRaw], b"hello");
+        assert!(diff(&expected, &expected.clone()).is_empty());
+
+        let mut actual = Frame::default();
+        actual.write_version(1);
+        actual.write_flags(&[Flag::Error]);
+        actual.write_options(&[1]).unwrap();
+        actual.write_payload(b"help!");
+        actual.write_crc();
+
+        assert_eq!(
+            diff(&expected, &actual),
+            vec![
+                Difference::Flags {
+                    expected: Flags::CODEC_RAW,
+                    actual: Flags::ERROR,
+                },
+                Difference::Options {
+                    expected: vec![],
+                    actual: vec![1],
+                },
+                Difference::Payload {
+                    offset: 3,
+                    expected_len: 5,
+                    actual_len: 5,
+                },
+            ]
+        );
+
+        let longer = frame(&[Flag::CodecRaw], b"hello world");
+        assert_eq!(
+            diff(&expected, &longer),
+            vec![Difference::Payload {
+                offset: 5,
+                expected_len: 5,
+                actual_len: 11,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_percentile() {
+        let mut latencies: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+        assert_eq!(
+            percentile(&mut latencies, 0.5),
+            Some(Duration::from_millis(51))
+        );
+        assert_eq!(
+            percentile(&mut latencies, 1.0),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(percentile(&mut [], 0.5), None);
+    }
+}
@@ -0,0 +1,34 @@
+// span helpers, everything is a no-op without the `tracing` feature
+
+/// Records the field of the current span, the value is not evaluated without the feature.
+macro_rules! record {
+    ($field:literal, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, $value);
+    };
+}
+
+pub(crate) use record;
+
+/// Records the `elapsed_us` field of the current span on drop.
+pub(crate) struct Elapsed {
+    #[cfg(feature = "tracing")]
+    start: std::time::Instant,
+}
+
+impl Elapsed {
+    #[inline]
+    pub(crate) fn start() -> Self {
+        Elapsed {
+            #[cfg(feature = "tracing")]
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Elapsed {
+    fn drop(&mut self) {
+        record!("elapsed_us", self.start.elapsed().as_micros() as u64);
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::ChildStderr;
+use tokio::task::JoinHandle;
+
+// stderr lines kept for the exit report
+pub const DEFAULT_STDERR_LINES: usize = 20;
+
+/// Error of the dead worker, downcast the `anyhow::Error` to get it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerExit {
+    pub pid: u32,
+    // None when killed by a signal
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    // last lines written to stderr, oldest first
+    pub stderr: Vec<String>,
+    pub uptime: Duration,
+    pub jobs: u64,
+}
+
+impl WorkerExit {
+    pub fn new(
+        pid: u32,
+        status: ExitStatus,
+        stderr: Vec<String>,
+        uptime: Duration,
+        jobs: u64,
+    ) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+
+        WorkerExit {
+            pid,
+            code: status.code(),
+            signal,
+            stderr,
+            uptime,
+            jobs,
+        }
+    }
+}
+
+/// Name of the common terminating signals.
+pub fn signal_name(signal: i32) -> Option<&'static str> {
+    let name = match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        _ => return None,
+    };
+    Some(name)
+}
+
+impl fmt::Display for WorkerExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "worker {} ", self.pid)?;
+
+        match (self.code, self.signal) {
+            (Some(code), _) => write!(f, "exited with code {}", code)?,
+            (None, Some(signal)) => {
+                write!(f, "killed by signal {}", signal)?;
+                if let Some(name) = signal_name(signal) {
+                    write!(f, " ({})", name)?;
+                }
+                // the OOM killer sends SIGKILL
+                if signal == 9 {
+                    write!(f, ", possibly OOM-killed")?;
+                }
+            }
+            (None, None) => write!(f, "exited")?,
+        }
+
+        write!(
+            f,
+            " after {:.3}s, {} jobs served",
+            self.uptime.as_secs_f64(),
+            self.jobs
+        )?;
+
+        match self.stderr.is_empty() {
+            true => write!(f, ", no stderr output"),
+            false => {
+                write!(f, ", stderr:")?;
+                for line in &self.stderr {
+                    write!(f, "\n  {}", line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorkerExit {}
+
+#[derive(Debug)]
+struct Tail {
+    lines: VecDeque<String>,
+    max: usize,
+}
+
+/// Drains the worker stderr in the background and keeps the last lines,
+/// otherwise the worker blocks once the stderr pipe is full.
+#[derive(Debug, Clone)]
+pub(crate) struct StderrTail {
+    tail: Arc<Mutex<Tail>>,
+}
+
+impl StderrTail {
+    pub(crate) fn spawn(stderr: ChildStderr) -> (Self, JoinHandle<()>) {
+        let tail = StderrTail {
+            tail: Arc::new(Mutex::new(Tail {
+                lines: VecDeque::new(),
+                max: DEFAULT_STDERR_LINES,
+            })),
+        };
+
+        let writer = tail.clone();
+        let handle = tokio::spawn(async move {
+            let mut reader = BufReader::new(stderr);
+            let mut line = vec![];
+
+            // ends on EOF, when the worker exits
+            while let Ok(n) = reader.read_until(b'\n', &mut line).await {
+                if n == 0 {
+                    break;
+                }
+
+                writer.push(String::from_utf8_lossy(&line).trim_end().to_string());
+                line.clear();
+            }
+        });
+
+        (tail, handle)
+    }
+
+    fn push(&self, line: String) {
+        let mut tail = self.tail.lock().unwrap();
+        tail.lines.push_back(line);
+        while tail.lines.len() > tail.max {
+            tail.lines.pop_front();
+        }
+    }
+
+    pub(crate) fn set_max(&self, max: usize) {
+        let mut tail = self.tail.lock().unwrap();
+        tail.max = max;
+        while tail.lines.len() > max {
+            tail.lines.pop_front();
+        }
+    }
+
+    pub(crate) fn lines(&self) -> Vec<String> {
+        self.tail.lock().unwrap().lines.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worker::exit::WorkerExit;
+    use std::time::Duration;
+
+    #[test]
+    fn test_worker_exit_display() {
+        let mut exit = WorkerExit {
+            pid: 42,
+            code: Some(255),
+            signal: None,
+            stderr: vec![
+                "PHP Fatal error:  Uncaught Error".to_string(),
+                "  thrown in worker.php on line 7".to_string(),
+            ],
+            uptime: Duration::from_millis(1500),
+            jobs: 10,
+        };
+
+        assert_eq!(
+            exit.to_string(),
+            "worker 42 exited with code 255 after 1.500s, 10 jobs served, stderr:\n  PHP Fatal error:  Uncaught Error\n    thrown in worker.php on line 7"
+        );
+
+        exit.code = None;
+        exit.signal = Some(9);
+        exit.stderr.clear();
+        assert_eq!(
+            exit.to_string(),
+            "worker 42 killed by signal 9 (SIGKILL), possibly OOM-killed after 1.500s, 10 jobs served, no stderr output"
+        );
+
+        exit.signal = Some(11);
+        assert!(
+            exit.to_string()
+                .contains("killed by signal 11 (SIGSEGV) after")
+        );
+    }
+}
@@ -0,0 +1,317 @@
+mod exit;
+mod spec;
+mod state;
+mod supervisor;
+
+use crate::frame::Frame;
+use crate::pipe::Pipes;
+use crate::pipe::commands::StopCommand;
+use anyhow::anyhow;
+use exit::StderrTail;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+pub use exit::{DEFAULT_STDERR_LINES, WorkerExit, signal_name};
+pub use spec::Spec;
+pub use state::{State, StateEvent, StateMachine};
+pub(crate) use supervisor::CrashLoop;
+pub use supervisor::{Backoff, BreakerState, CircuitBreaker, Supervisor};
+
+// time given to the worker to exit after the stop command
+pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// time given to the worker to acknowledge the cancel command
+pub const DEFAULT_CANCEL_TIMEOUT: Duration = Duration::from_secs(1);
+
+// stdout is closed slightly before the dead process can be reaped
+const EXIT_GRACE: Duration = Duration::from_millis(100);
+
+/// Worker process with the lifecycle state, every state change is published to the subscribers.
+pub struct Worker {
+    pipes: Pipes,
+    state: StateMachine,
+    pid: u32,
+    // OS pid of the spawned process, the handshake pid could differ behind a wrapper
+    process_id: u32,
+    created: Instant,
+    jobs: u64,
+    stop_timeout: Duration,
+    cancel_timeout: Duration,
+    stderr: Option<StderrTail>,
+    stderr_task: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Spawns the process and does the PID handshake.
+    pub async fn spawn(cmd: &[&str]) -> anyhow::Result<Self> {
+        Worker::from_spec(&Spec::new(cmd)).await
+    }
+
+    pub async fn from_spec(spec: &Spec) -> anyhow::Result<Self> {
+        let mut worker = Worker::new(spec.spawn().await?);
+        worker.start().await?;
+        Ok(worker)
+    }
+
+    /// Inactive worker over the spawned process, `start` does the PID handshake.
+    /// The stderr is drained in the background, the last lines are kept for the exit report.
+    pub fn new(mut pipes: Pipes) -> Self {
+        let (stderr, stderr_task) = match pipes.take_stderr() {
+            Some(stderr) => {
+                let (tail, task) = StderrTail::spawn(stderr);
+                (Some(tail), Some(task))
+            }
+            None => (None, None),
+        };
+
+        Worker {
+            pipes,
+            state: StateMachine::default(),
+            pid: 0,
+            process_id: 0,
+            created: Instant::now(),
+            jobs: 0,
+            stop_timeout: DEFAULT_STOP_TIMEOUT,
+            cancel_timeout: DEFAULT_CANCEL_TIMEOUT,
+            stderr,
+            stderr_task,
+        }
+    }
+
+    pub async fn start(&mut self) -> anyhow::Result<()> {
+        if self.state() != State::Inactive {
+            return Err(anyhow!(
+                "worker is already started, state is {}",
+                self.state()
+            ));
+        }
+
+        self.process_id = self.pipes.id().await.unwrap_or_default();
+        match self.pipes.send_pid().await {
+            Ok(pid) => {
+                self.pid = pid;
+                self.state.transition(State::Ready)
+            }
+            Err(err) => {
+                self.state.transition(State::Errored)?;
+                Err(self.exit_error(err).await)
+            }
+        }
+    }
+
+    pub fn set_stop_timeout(&mut self, stop_timeout: Duration) {
+        self.stop_timeout = stop_timeout;
+    }
+
+    pub fn set_cancel_timeout(&mut self, cancel_timeout: Duration) {
+        self.cancel_timeout = cancel_timeout;
+    }
+
+    /// Number of the last stderr lines kept, `DEFAULT_STDERR_LINES` by default.
+    pub fn set_stderr_lines(&mut self, lines: usize) {
+        if let Some(stderr) = &self.stderr {
+            stderr.set_max(lines);
+        }
+    }
+
+    /// Last lines written to stderr, oldest first.
+    pub fn stderr(&self) -> Vec<String> {
+        self.stderr.as_ref().map(|s| s.lines()).unwrap_or_default()
+    }
+
+    /// Exit report, None until the process is reaped.
+    pub fn exit(&self) -> Option<WorkerExit> {
+        let status = self.pipes.status()?;
+        Some(WorkerExit::new(
+            self.process_id,
+            status,
+            self.stderr(),
+            self.uptime(),
+            self.jobs,
+        ))
+    }
+
+    #[inline]
+    pub fn state(&self) -> State {
+        self.state.state()
+    }
+
+    /// Latest state change.
+    #[inline]
+    pub fn event(&self) -> StateEvent {
+        self.state.event()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<StateEvent> {
+        self.state.subscribe()
+    }
+
+    /// Validated transition, e.g. `Invalid` to take the worker out of service.
+    pub fn set_state(&mut self, state: State) -> anyhow::Result<()> {
+        self.state.transition(state)
+    }
+
+    /// PID reported by the worker in the handshake, 0 before the handshake.
+    #[inline]
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Number of the successfully executed jobs.
+    #[inline]
+    pub fn jobs(&self) -> u64 {
+        self.jobs
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    pub fn pipes_mut(&mut self) -> &mut Pipes {
+        &mut self.pipes
+    }
+
+    /// Executes the job on the ready worker, any failure puts the worker into the `Errored` state.
+    pub async fn exec(&mut self, frame: &mut Frame) -> anyhow::Result<Frame> {
+        if self.state() != State::Ready {
+            return Err(anyhow!("worker is not ready, state is {}", self.state()));
+        }
+
+        self.state.transition(State::Working)?;
+        match self.pipes.exec(frame).await {
+            Ok(response) => {
+                self.jobs += 1;
+                self.state.transition(State::Ready)?;
+                Ok(response)
+            }
+            Err(err) => {
+                self.state.transition(State::Errored)?;
+                Err(self.exit_error(err).await)
+            }
+        }
+    }
+
+    // the error of the dead worker is replaced by the `WorkerExit`, the other errors are kept
+    async fn exit_error(&mut self, err: anyhow::Error) -> anyhow::Error {
+        let exited = match self.pipes.try_wait().await {
+            Ok(Some(_)) => true,
+            // only the closed pipe means the worker is exiting, the live one isn't waited for
+            Ok(None) if pipe_closed(&err) => {
+                matches!(timeout(EXIT_GRACE, self.pipes.wait()).await, Ok(Ok(_)))
+            }
+            _ => false,
+        };
+        if !exited {
+            return err;
+        }
+
+        // the last lines are read until the stderr EOF
+        if let Some(task) = self.stderr_task.take() {
+            _ = timeout(EXIT_GRACE, task).await;
+        }
+
+        match self.exit() {
+            Some(exit) => exit.into(),
+            None => err,
+        }
+    }
+
+    /// Cancels the job left `Working` by the dropped exec: sends the cancel command and discards
+    /// the frames until the acknowledgement, the worker is `Ready` again then. The worker that
+    /// got the partial request, sent the partially read response or didn't acknowledge
+    /// in the cancel timeout is marked `Invalid` to be recycled. The job is aborted only
+    /// by the workers implementing `CancelCommand`, the others are recycled once they exit
+    /// or the timeout ends.
+    pub async fn cancel(&mut self) -> anyhow::Result<()> {
+        if self.state() != State::Working {
+            return Err(anyhow!("no job to cancel, state is {}", self.state()));
+        }
+
+        if self.pipes.write_interrupted() {
+            self.state.transition(State::Invalid)?;
+            return Err(anyhow!(
+                "worker {} got the partial request, can't be cancelled",
+                self.pid
+            ));
+        }
+
+        // the rest of the response would be taken for the frame header
+        if self.pipes.read_interrupted() {
+            self.state.transition(State::Invalid)?;
+            return Err(anyhow!(
+                "worker {} response was partially read, can't be cancelled",
+                self.pid
+            ));
+        }
+
+        match timeout(self.cancel_timeout, self.pipes.cancel()).await {
+            Ok(Ok(_)) => self.state.transition(State::Ready),
+            Ok(Err(err)) => {
+                self.state.transition(State::Invalid)?;
+                Err(err)
+            }
+            Err(_) => {
+                self.state.transition(State::Invalid)?;
+                Err(anyhow!(
+                    "worker {} did not acknowledge the cancel in {:?}",
+                    self.pid,
+                    self.cancel_timeout
+                ))
+            }
+        }
+    }
+
+    /// Sends the stop command and waits for the exit, the worker is killed after the stop timeout.
+    pub async fn stop(&mut self) -> anyhow::Result<()> {
+        self.state.transition(State::Stopping)?;
+
+        if let Err(err) = self.pipes.send_control(StopCommand::default()).await {
+            self.destroy().await?;
+            return Err(err);
+        }
+
+        match timeout(self.stop_timeout, self.pipes.wait()).await {
+            Ok(Ok(_)) => self.state.transition(State::Stopped),
+            Ok(Err(err)) => {
+                self.state.transition(State::Errored)?;
+                Err(err)
+            }
+            Err(_) => {
+                self.destroy().await?;
+                Err(anyhow!(
+                    "worker {} did not stop in {:?}, killed",
+                    self.pid,
+                    self.stop_timeout
+                ))
+            }
+        }
+    }
+
+    /// Kills the process if it is still running and reaps it.
+    pub async fn destroy(&mut self) -> anyhow::Result<()> {
+        if self.state() == State::Destroyed {
+            return Ok(());
+        }
+
+        if self.pipes.try_wait().await?.is_none() {
+            self.pipes.kill().await?;
+        }
+
+        self.state.transition(State::Destroyed)
+    }
+}
+
+// broken pipe or the EOF in the middle of the frame, the worker has closed its end
+fn pipe_closed(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause.downcast_ref::<std::io::Error>().is_some_and(|err| {
+            matches!(
+                err.kind(),
+                ErrorKind::BrokenPipe | ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset
+            )
+        })
+    })
+}
@@ -0,0 +1,78 @@
+use crate::pipe::Pipes;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+/// How to spawn the worker process, kept to respawn the same worker.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Spec {
+    pub command: Vec<String>,
+    // added to the parent environment
+    pub env: Vec<(String, String)>,
+    pub dir: Option<PathBuf>,
+}
+
+impl Spec {
+    pub fn new(command: &[&str]) -> Self {
+        Spec {
+            command: command.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        self.env.push((key.to_string(), value.to_string()));
+    }
+
+    pub fn set_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.dir = Some(dir.into());
+    }
+
+    /// The process is killed when the command handle is dropped, the worker never outlives the parent.
+    pub fn command(&self) -> anyhow::Result<Command> {
+        let Some((program, args)) = self.command.split_first() else {
+            anyhow::bail!("empty worker command");
+        };
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .kill_on_drop(true);
+
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+
+        Ok(command)
+    }
+
+    pub async fn spawn(&self) -> anyhow::Result<Pipes> {
+        Pipes::from_command(self.command()?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worker::spec::Spec;
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    #[test]
+    fn test_spec_command() {
+        let mut spec = Spec::new(&["php", "worker.php"]);
+        spec.set_env("RR_MODE", "http");
+        spec.set_dir("/app");
+
+        let command = spec.command().unwrap();
+        let command = command.as_std();
+        assert_eq!(command.get_program(), "php");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["worker.php"]);
+        assert_eq!(
+            command.get_envs().collect::<Vec<_>>(),
+            [(OsStr::new("RR_MODE"), Some(OsStr::new("http")))]
+        );
+        assert_eq!(command.get_current_dir(), Some(Path::new("/app")));
+
+        assert!(Spec::default().command().is_err());
+    }
+}
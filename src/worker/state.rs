@@ -0,0 +1,205 @@
+use anyhow::anyhow;
+use std::fmt;
+use std::time::{Instant, SystemTime};
+use tokio::sync::watch;
+
+/// Worker lifecycle states, same as the RoadRunner ones.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum State {
+    // spawned, the PID handshake is not done yet
+    Inactive,
+    // waiting for the job
+    Ready,
+    // executing the job
+    Working,
+    // should not get the jobs anymore and is going to be stopped (max jobs, memory, reset)
+    Invalid,
+    // the stop command is sent, waiting for the process to exit
+    Stopping,
+    // exited after the stop command
+    Stopped,
+    // protocol or process failure, the worker is unusable
+    Errored,
+    // the process is killed and reaped
+    Destroyed,
+}
+
+impl State {
+    /// Whether the transition from `self` to `to` is allowed.
+    pub fn can_transition(self, to: State) -> bool {
+        use State::*;
+
+        matches!(
+            (self, to),
+            (Inactive, Ready | Errored | Destroyed)
+                | (Ready, Working | Invalid | Stopping | Errored | Destroyed)
+                | (Working, Ready | Invalid | Errored | Destroyed)
+                | (Invalid, Stopping | Errored | Destroyed)
+                | (Stopping, Stopped | Errored | Destroyed)
+                | (Stopped | Errored, Destroyed)
+        )
+    }
+
+    /// No transitions out of the state except `Destroyed`, the process is gone or unusable.
+    pub fn is_terminal(self) -> bool {
+        matches!(self, State::Stopped | State::Errored | State::Destroyed)
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            State::Inactive => "inactive",
+            State::Ready => "ready",
+            State::Working => "working",
+            State::Invalid => "invalid",
+            State::Stopping => "stopping",
+            State::Stopped => "stopped",
+            State::Errored => "errored",
+            State::Destroyed => "destroyed",
+        };
+        f.write_str(s)
+    }
+}
+
+/// State change published to the subscribers.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StateEvent {
+    pub state: State,
+    // None for the initial state
+    pub previous: Option<State>,
+    pub at: SystemTime,
+    // number of the transitions before this one
+    pub seq: u64,
+}
+
+/// Validates the transitions and publishes every change to the `watch` subscribers.
+#[derive(Debug)]
+pub struct StateMachine {
+    tx: watch::Sender<StateEvent>,
+    // monotonic time of the last transition
+    since: Instant,
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new(State::Inactive)
+    }
+}
+
+impl StateMachine {
+    pub fn new(state: State) -> Self {
+        let (tx, _) = watch::channel(StateEvent {
+            state,
+            previous: None,
+            at: SystemTime::now(),
+            seq: 0,
+        });
+
+        StateMachine {
+            tx,
+            since: Instant::now(),
+        }
+    }
+
+    #[inline]
+    pub fn state(&self) -> State {
+        self.tx.borrow().state
+    }
+
+    #[inline]
+    pub fn event(&self) -> StateEvent {
+        *self.tx.borrow()
+    }
+
+    /// Time spent in the current state.
+    pub fn elapsed(&self) -> std::time::Duration {
+        self.since.elapsed()
+    }
+
+    pub fn transition(&mut self, to: State) -> anyhow::Result<()> {
+        let from = self.state();
+        if !from.can_transition(to) {
+            return Err(anyhow!(
+                "invalid worker state transition: {} -> {}",
+                from,
+                to
+            ));
+        }
+
+        let seq = self.tx.borrow().seq + 1;
+        self.tx.send_replace(StateEvent {
+            state: to,
+            previous: Some(from),
+            at: SystemTime::now(),
+            seq,
+        });
+        self.since = Instant::now();
+
+        Ok(())
+    }
+
+    /// The receiver sees the latest state, intermediate states could be skipped by a slow subscriber,
+    /// `StateEvent::seq` tells how many.
+    pub fn subscribe(&self) -> watch::Receiver<StateEvent> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worker::state::{State, StateMachine};
+
+    #[test]
+    fn test_transitions() {
+        use State::*;
+
+        let mut sm = StateMachine::default();
+        assert_eq!(sm.state(), Inactive);
+        assert_eq!(sm.event().previous, None);
+
+        for to in [Ready, Working, Ready, Invalid, Stopping, Stopped, Destroyed] {
+            sm.transition(to).unwrap();
+            assert_eq!(sm.state(), to);
+        }
+        assert_eq!(sm.event().seq, 7);
+        assert_eq!(sm.event().previous, Some(Stopped));
+
+        // nothing after the destroyed
+        let err = sm.transition(Ready).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid worker state transition: destroyed -> ready"
+        );
+        assert_eq!(sm.state(), Destroyed);
+
+        let mut sm = StateMachine::default();
+        assert!(sm.transition(Working).is_err());
+        sm.transition(Ready).unwrap();
+        assert!(sm.transition(Stopped).is_err());
+        sm.transition(Errored).unwrap();
+        assert!(sm.state().is_terminal());
+        assert!(sm.transition(Ready).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let mut sm = StateMachine::default();
+        let mut rx = sm.subscribe();
+
+        sm.transition(State::Ready).unwrap();
+        rx.changed().await.unwrap();
+        let event = *rx.borrow_and_update();
+        assert_eq!(event.state, State::Ready);
+        assert_eq!(event.previous, Some(State::Inactive));
+
+        // the slow subscriber sees the latest state
+        sm.transition(State::Working).unwrap();
+        sm.transition(State::Ready).unwrap();
+        rx.changed().await.unwrap();
+        assert_eq!(rx.borrow_and_update().seq, 3);
+
+        drop(sm);
+        assert!(rx.changed().await.is_err());
+    }
+}
@@ -0,0 +1,366 @@
+use crate::frame::Frame;
+use crate::metrics;
+use crate::worker::{Spec, State, Worker, WorkerExit};
+use anyhow::anyhow;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{Instant, sleep};
+
+/// Exponential backoff between the respawns of the crash looping worker.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+    // the delay is randomized by +-jitter fraction, so the crashed workers don't respawn in lockstep
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the respawn after `crashes` consecutive crashes, `random` is in 0.0..1.0.
+    /// The first crash is respawned immediately.
+    pub fn delay(&self, crashes: u32, random: f64) -> Duration {
+        if crashes <= 1 {
+            return Duration::ZERO;
+        }
+
+        let max = self.max.as_secs_f64();
+        let base = (self.initial.as_secs_f64() * self.multiplier.powi(crashes as i32 - 2)).min(max);
+        let jitter = base * self.jitter * (random * 2.0 - 1.0);
+
+        Duration::from_secs_f64((base + jitter).clamp(0.0, max))
+    }
+}
+
+/// Stops the respawns when the worker crashes `max_crashes` times within the `window`,
+/// a single respawn is tried again after the `cooldown`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    pub max_crashes: u32,
+    pub window: Duration,
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker {
+            max_crashes: 5,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BreakerState {
+    Closed,
+    // no respawns until the cooldown ends
+    Open,
+    // the cooldown ended, the next crash opens the breaker again, the served job closes it
+    HalfOpen,
+}
+
+// recent crashes driving the backoff and the circuit breaker, shared with the pool allocations
+#[derive(Debug)]
+pub(crate) struct CrashLoop {
+    // crashes within the breaker window
+    crashes: VecDeque<Instant>,
+    // crashes since the last success
+    consecutive: u32,
+    open_until: Option<Instant>,
+    rng: u64,
+}
+
+impl CrashLoop {
+    pub(crate) fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+
+        CrashLoop {
+            crashes: VecDeque::new(),
+            consecutive: 0,
+            open_until: None,
+            rng: seed | 1,
+        }
+    }
+
+    pub(crate) fn state(&self) -> BreakerState {
+        match self.open_until {
+            None => BreakerState::Closed,
+            Some(until) if Instant::now() < until => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    // end of the cooldown of the open breaker
+    #[inline]
+    pub(crate) fn open_until(&self) -> Option<Instant> {
+        self.open_until
+    }
+
+    // crashes within the breaker window
+    #[inline]
+    pub(crate) fn crashes(&self) -> usize {
+        self.crashes.len()
+    }
+
+    // delay before the next try
+    pub(crate) fn delay(&mut self, backoff: &Backoff) -> Duration {
+        let random = self.next_random();
+        backoff.delay(self.consecutive, random)
+    }
+
+    pub(crate) fn record(&mut self, breaker: &CircuitBreaker) {
+        let now = Instant::now();
+        self.consecutive += 1;
+        self.crashes.push_back(now);
+        while let Some(&at) = self.crashes.front() {
+            if now.duration_since(at) <= breaker.window {
+                break;
+            }
+            self.crashes.pop_front();
+        }
+
+        let half_open = self.state() == BreakerState::HalfOpen;
+        if half_open || self.crashes.len() >= breaker.max_crashes as usize {
+            self.open_until = Some(now + breaker.cooldown);
+        }
+    }
+
+    // the half-open breaker is closed by the success
+    pub(crate) fn succeeded(&mut self) {
+        self.consecutive = 0;
+        if self.open_until.is_some() {
+            self.open_until = None;
+            self.crashes.clear();
+        }
+    }
+
+    // xorshift64, only for the jitter
+    fn next_random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Self-healing worker: the crashed worker is respawned from the spec on the next exec.
+/// The job the worker crashed on is not retried, the error is returned to the caller.
+pub struct Supervisor {
+    spec: Spec,
+    worker: Option<Worker>,
+    backoff: Backoff,
+    breaker: CircuitBreaker,
+    crash_loop: CrashLoop,
+    spawned: u64,
+    last_exit: Option<WorkerExit>,
+}
+
+impl Supervisor {
+    pub fn new(spec: Spec) -> Self {
+        Supervisor {
+            spec,
+            worker: None,
+            backoff: Backoff::default(),
+            breaker: CircuitBreaker::default(),
+            crash_loop: CrashLoop::new(),
+            spawned: 0,
+            last_exit: None,
+        }
+    }
+
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    pub fn set_circuit_breaker(&mut self, breaker: CircuitBreaker) {
+        self.breaker = breaker;
+    }
+
+    #[inline]
+    pub fn spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    /// Number of the respawns, the first spawn is not counted.
+    #[inline]
+    pub fn restarts(&self) -> u64 {
+        self.spawned.saturating_sub(1)
+    }
+
+    pub fn worker(&self) -> Option<&Worker> {
+        self.worker.as_ref()
+    }
+
+    pub fn worker_mut(&mut self) -> Option<&mut Worker> {
+        self.worker.as_mut()
+    }
+
+    /// Exit report of the last worker that died on its own.
+    pub fn last_exit(&self) -> Option<&WorkerExit> {
+        self.last_exit.as_ref()
+    }
+
+    pub fn breaker_state(&self) -> BreakerState {
+        self.crash_loop.state()
+    }
+
+    /// Returns the ready worker, the dead one is replaced first.
+    pub async fn ensure_worker(&mut self) -> anyhow::Result<&mut Worker> {
+        if let Some(worker) = &mut self.worker {
+            // could have died between the jobs
+            let exited = worker.pipes_mut().try_wait().await?.is_some();
+            match (exited, worker.state()) {
+                (false, State::Ready) => {}
+                // the exec was dropped in the middle of the job
+                (false, State::Working) => {
+                    if worker.cancel().await.is_err() {
+                        // recycled, not a crash
+                        _ = worker.destroy().await;
+                        self.worker = None;
+                    }
+                }
+                // taken out of service, not a crash; killed if it doesn't stop
+                (false, State::Invalid) => _ = self.stop().await,
+                _ => self.crashed().await,
+            }
+        }
+
+        if self.worker.is_none() {
+            self.respawn().await?;
+        }
+
+        Ok(self.worker.as_mut().unwrap())
+    }
+
+    /// Executes the job, the dead worker is replaced before the job is sent.
+    pub async fn exec(&mut self, frame: &mut Frame) -> anyhow::Result<Frame> {
+        let worker = self.ensure_worker().await?;
+
+        match worker.exec(frame).await {
+            Ok(response) => {
+                // the half-open breaker is closed by the served job
+                self.crash_loop.succeeded();
+                Ok(response)
+            }
+            Err(err) => {
+                self.crashed().await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Stops the worker, it is not respawned until the next exec.
+    pub async fn stop(&mut self) -> anyhow::Result<()> {
+        match self.worker.take() {
+            Some(mut worker) => worker.stop().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn respawn(&mut self) -> anyhow::Result<()> {
+        if self.breaker_state() == BreakerState::Open {
+            let last = match &self.last_exit {
+                Some(exit) => format!(", last crash: {}", exit),
+                None => String::new(),
+            };
+            return Err(anyhow!(
+                "circuit breaker is open: {} crashes in {:?}, command: {}{}",
+                self.crash_loop.crashes(),
+                self.breaker.window,
+                self.spec.command.join(" "),
+                last
+            ));
+        }
+
+        let delay = self.crash_loop.delay(&self.backoff);
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+
+        if self.spawned > 0 {
+            metrics::worker_restarted();
+        }
+        self.spawned += 1;
+
+        match Worker::from_spec(&self.spec).await {
+            Ok(worker) => {
+                self.worker = Some(worker);
+                Ok(())
+            }
+            Err(err) => {
+                if let Some(exit) = err.downcast_ref::<WorkerExit>() {
+                    self.last_exit = Some(exit.clone());
+                }
+                self.crash_loop.record(&self.breaker);
+                Err(err)
+            }
+        }
+    }
+
+    async fn crashed(&mut self) {
+        if let Some(mut worker) = self.worker.take() {
+            // only the exit on its own, not the kill below
+            if let Some(exit) = worker.exit() {
+                self.last_exit = Some(exit);
+            }
+            // reaped anyway, nothing to do with the kill error
+            _ = worker.destroy().await;
+        }
+
+        self.crash_loop.record(&self.breaker);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worker::supervisor::Backoff;
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.0,
+        };
+
+        assert_eq!(backoff.delay(0, 0.5), Duration::ZERO);
+        assert_eq!(backoff.delay(1, 0.5), Duration::ZERO);
+        assert_eq!(backoff.delay(2, 0.5), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3, 0.5), Duration::from_millis(200));
+        assert_eq!(backoff.delay(4, 0.5), Duration::from_millis(400));
+        assert_eq!(backoff.delay(10, 0.5), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let backoff = Backoff {
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        let low = backoff.delay(2, 0.0);
+        let high = backoff.delay(2, 0.99);
+        assert_eq!(low, Duration::from_millis(50));
+        assert!(high > Duration::from_millis(145) && high < Duration::from_millis(150));
+
+        // capped by the max with the jitter too
+        assert!(backoff.delay(100, 0.99) <= backoff.max);
+    }
+}
@@ -0,0 +1,30 @@
+use std::process::Command;
+
+const BENCH: &str = env!("CARGO_BIN_EXE_goridge-bench");
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+
+#[test]
+fn test_bench_report() {
+    let out = Command::new(BENCH)
+        .args(["--workers", "3", "--requests", "50", "--payload", "100"])
+        .args(["--", WORKER])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert!(out.status.success());
+    assert!(stdout.starts_with("3 workers, 150 requests, 100 bytes payload"));
+    assert!(stdout.contains("throughput: "));
+    assert!(stdout.contains("latency p999: "));
+    assert!(stdout.contains("histogram:"));
+}
+
+#[test]
+fn test_bench_bad_worker() {
+    let out = Command::new(BENCH)
+        .args(["--requests", "10", "--", WORKER, "--error-every", "1"])
+        .output()
+        .unwrap();
+
+    assert!(!out.status.success());
+}
@@ -0,0 +1,24 @@
+use goridge_rs::frame::Frame;
+use goridge_rs::frame::frame_flags::Flag;
+
+/// Raw-codec frame of the protocol version 1 with the header CRC.
+pub fn frame(payload: &[u8]) -> Frame {
+    let mut frame = Frame::default();
+    frame.write_version(1);
+    frame.write_flags(&[Flag::CodecRaw]);
+    frame.write_payload(payload);
+    frame.write_crc();
+    frame
+}
+
+/// Waits for the condition checked every 10ms, fails the test after 10s.
+#[allow(dead_code)] // not every test binary waits for the state
+pub async fn eventually(mut condition: impl FnMut() -> bool) {
+    for _ in 0..1000 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("the condition is not met in 10s");
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::frame;
+use goridge_rs::frame::frame_flags::Flags;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const DUMP: &str = env!("CARGO_BIN_EXE_goridge-dump");
+
+fn dump(data: &[u8]) -> Output {
+    let mut child = Command::new(DUMP)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(data).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_dump_frames() {
+    let mut pid = frame(br#"{"pid":42}"#);
+    pid.set_flags(Flags::CONTROL | Flags::CODEC_JSON);
+    pid.write_crc();
+
+    let mut hello = frame(b"hello");
+    hello.write_options(&[7, 8]).unwrap();
+    hello.write_crc();
+
+    let mut data = pid.bytes();
+    data.extend(hello.bytes());
+
+    let out = dump(&data);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert!(out.status.success());
+    assert!(stdout.contains("frame #0 at offset 0"));
+    assert!(stdout.contains("flags: Flags(CONTROL | CODEC_JSON)"));
+    assert!(stdout.contains("  payload:\n    {\n      \"pid\": 42\n    }"));
+    assert!(stdout.contains("frame #1 at offset 22"));
+    assert!(stdout.contains("options: [7, 8]"));
+    assert!(stdout.contains(" (valid)\n"));
+    assert!(stdout.contains("payload: \"hello\""));
+    assert!(stdout.ends_with("2 frames\n"));
+}
+
+#[test]
+fn test_dump_corruption() {
+    let mut data = frame(b"hello").bytes();
+    data.extend(b"warning: some weird php error");
+    data.extend(frame(b"world").bytes());
+
+    let out = dump(&data);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    assert!(stdout.contains("corrupted data at offset 17"));
+    assert!(stdout.contains("skipped 29 bytes: \"warning: some weird php error\""));
+    assert!(stdout.contains("frame #1 at offset 46"));
+    assert!(stdout.contains("payload: \"world\""));
+}
+
+#[test]
+fn test_dump_long_garbage() {
+    // spans several reads, dropped as it is scanned
+    let mut data = frame(b"hello").bytes();
+    data.extend(vec![b'x'; 200_000]);
+    data.extend(frame(b"world").bytes());
+
+    let out = dump(&data);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    assert!(stdout.contains("corrupted data at offset 17"));
+    assert!(stdout.contains("skipped 200000 bytes: \"xxxx"));
+    assert!(stdout.contains("... (199744 more bytes)"));
+    assert!(stdout.contains("frame #1 at offset 200017"));
+    assert!(stdout.ends_with("2 frames\n"));
+}
+
+#[test]
+fn test_dump_truncated() {
+    let data = frame(b"hello").bytes();
+
+    let out = dump(&data[..data.len() - 2]);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    assert!(stdout.contains("truncated frame at offset 0: stream ended after 15 bytes"));
+    assert!(stdout.ends_with("0 frames\n"));
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "prometheus")]
+
+mod common;
+
+use common::frame;
+use goridge_rs::metrics::install_prometheus;
+use goridge_rs::pipe::Pipes;
+
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+
+// the recorder is global, everything is checked in the single test
+#[tokio::test(start_paused = true)]
+async fn test_prometheus_metrics() {
+    let handle = install_prometheus().unwrap();
+
+    let mut p = Pipes::new(&[WORKER, "--noise-every", "4"]).await.unwrap();
+    p.set_name("test");
+    let pid = p.send_pid().await.unwrap();
+
+    for _ in 0..3 {
+        p.exec(&mut frame(b"hello")).await.unwrap();
+    }
+    // the noise before the response
+    assert!(p.exec(&mut frame(b"hello")).await.is_err());
+
+    p.kill().await.unwrap();
+    p.wait().await.unwrap();
+    // reaped once, counted once
+    p.try_wait().await.unwrap();
+
+    let rendered = handle.render();
+    let has = |line: &str| {
+        assert!(
+            rendered.lines().any(|l| l == line),
+            "no `{}` in:\n{}",
+            line,
+            rendered
+        )
+    };
+
+    let pid_frame_len = |pid: u32| 12 + format!(r#"{{"pid":{},"versions":[1]}}"#, pid).len();
+    let job_frame_len = 12 + 5;
+
+    // PID request and 4 jobs
+    has("goridge_frames_sent_total{relay=\"test\"} 5");
+    has(&format!(
+        "goridge_bytes_sent_total{{relay=\"test\"}} {}",
+        pid_frame_len(std::process::id()) + 4 * job_frame_len
+    ));
+    // PID response and 3 jobs
+    has("goridge_frames_received_total{relay=\"test\"} 4");
+    has(&format!(
+        "goridge_bytes_received_total{{relay=\"test\"}} {}",
+        pid_frame_len(pid) + 3 * job_frame_len
+    ));
+    has("goridge_decode_errors_total{relay=\"test\",kind=\"header_crc\"} 1");
+    has("goridge_exec_duration_seconds_count{relay=\"test\"} 3");
+    assert!(rendered.contains("goridge_exec_duration_seconds_bucket{relay=\"test\",le=\"0.1\"}"));
+    has("goridge_workers_spawned_total 1");
+    has("goridge_workers_exited_total{status=\"signal\"} 1");
+    assert!(rendered.contains("# HELP goridge_frames_sent_total Frames sent by the relay"));
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "otel")]
+
+use goridge_rs::payload::Payload;
+use goridge_rs::pipe::Pipes;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::{Context, global};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+
+#[tokio::test]
+async fn test_trace_context_round_trip() {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let span = SpanContext::new(
+        TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+        SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+        TraceFlags::SAMPLED,
+        true,
+        TraceState::default(),
+    );
+    let cx = Context::new().with_remote_span_context(span.clone());
+
+    let mut p = Pipes::new(&[WORKER]).await.unwrap();
+    p.send_pid().await.unwrap();
+
+    // the echo worker sends the context back, like a worker continuing the trace
+    p.send_payload_with_context(Payload::new(b"", b"hello"), &cx)
+        .await
+        .unwrap();
+    let (payload, response_cx) = p.receive_payload_with_context().await.unwrap();
+
+    assert_eq!(payload.body, b"hello");
+    assert_eq!(
+        response_cx.span().span_context().trace_id(),
+        span.trace_id()
+    );
+    assert_eq!(response_cx.span().span_context().span_id(), span.span_id());
+}
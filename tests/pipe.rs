@@ -0,0 +1,148 @@
+mod common;
+
+use common::frame;
+use goridge_rs::frame::frame_flags::Flags;
+use goridge_rs::frame::integrity::Integrity;
+use goridge_rs::payload::Payload;
+use goridge_rs::pipe::Pipes;
+use goridge_rs::pipe::commands::StopCommand;
+use std::time::{Duration, Instant};
+
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+
+async fn worker(args: &[&str]) -> Pipes {
+    let mut cmd = vec![WORKER];
+    cmd.extend_from_slice(args);
+
+    let mut p = Pipes::new(&cmd).await.unwrap();
+    assert_eq!(p.send_pid().await.unwrap(), p.id().await.unwrap());
+    p
+}
+
+#[tokio::test]
+async fn test1() {
+    let mut p = Pipes::new(&[WORKER]).await.unwrap();
+    let payload = vec![b'h', b'e', b'l', b'l', b'o'];
+
+    let mut request = frame(&payload);
+    request.write_options(&[0]).unwrap();
+    request.write_crc();
+    p.send(&mut request).await.unwrap();
+
+    let data = p.receive_stdout().await.unwrap();
+    assert_eq!(data.payload(), &payload);
+    assert_eq!(data.read_options().unwrap(), vec![0]);
+}
+
+#[tokio::test]
+async fn test_echo_many() {
+    let mut p = worker(&[]).await;
+
+    for i in 0..100 {
+        let payload = format!("hello {}", i);
+        p.send(&mut frame(payload.as_bytes())).await.unwrap();
+
+        let data = p.receive_stdout().await.unwrap();
+        assert_eq!(data.payload(), payload.as_bytes());
+        assert_eq!(data.read_flags(), Flags::CODEC_RAW);
+    }
+}
+
+#[tokio::test]
+async fn test_stop() {
+    let mut p = worker(&[]).await;
+
+    p.send_control(StopCommand::default()).await.unwrap();
+    p.wait().await.unwrap();
+    assert!(p.try_wait().await.unwrap().unwrap().success());
+}
+
+#[tokio::test]
+async fn test_delay() {
+    let mut p = worker(&["--delay-ms", "100"]).await;
+
+    let start = Instant::now();
+    p.send(&mut frame(b"hello")).await.unwrap();
+    p.receive_stdout().await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn test_injected_error() {
+    let mut p = worker(&["--error-every", "2"]).await;
+
+    p.send(&mut frame(b"hello")).await.unwrap();
+    assert!(
+        !p.receive_stdout()
+            .await
+            .unwrap()
+            .read_flags()
+            .contains(Flags::ERROR)
+    );
+
+    p.send(&mut frame(b"hello")).await.unwrap();
+    let data = p.receive_stdout().await.unwrap();
+    assert!(data.read_flags().contains(Flags::ERROR));
+    assert_eq!(data.payload(), b"injected error on the job 2");
+}
+
+#[tokio::test]
+async fn test_stdout_noise() {
+    let mut p = worker(&["--noise-every", "1", "--seed", "42"]).await;
+
+    p.send(&mut frame(b"hello")).await.unwrap();
+    assert!(p.receive_stdout().await.is_err());
+}
+
+#[tokio::test]
+async fn test_exit_after() {
+    let mut p = worker(&["--exit-after", "2", "--exit-code", "3"]).await;
+
+    p.send(&mut frame(b"hello")).await.unwrap();
+    assert!(p.receive_stdout().await.is_ok());
+
+    p.send(&mut frame(b"hello")).await.unwrap();
+    assert!(p.receive_stdout().await.is_err());
+
+    p.wait().await.unwrap();
+    assert_eq!(p.try_wait().await.unwrap().unwrap().code(), Some(3));
+}
+
+#[tokio::test]
+async fn test_payload_integrity() {
+    let mut p = Pipes::new(&[WORKER, "--integrity", "payload"])
+        .await
+        .unwrap();
+    p.set_integrity(Integrity::HeaderPayload);
+    p.send_pid().await.unwrap();
+
+    // the user options are kept, the payload CRC option is stripped
+    let mut request = frame(b"hello");
+    request.write_options(&[0]).unwrap();
+    request.write_crc();
+    p.send(&mut request).await.unwrap();
+    let data = p.receive_stdout().await.unwrap();
+    assert_eq!(data.payload(), b"hello");
+    assert_eq!(data.read_options().unwrap(), vec![0]);
+}
+
+#[tokio::test]
+async fn test_exec() {
+    let mut p = worker(&[]).await;
+
+    let data = p.exec(&mut frame(b"hello")).await.unwrap();
+    assert_eq!(data.payload(), b"hello");
+}
+
+#[tokio::test]
+async fn test_payload() {
+    let mut p = worker(&["--error-every", "2"]).await;
+
+    let payload = Payload::new(br#"{"headers":{}}"#, b"hello");
+    p.send_payload(&payload).await.unwrap();
+    assert_eq!(p.receive_payload().await.unwrap(), payload);
+
+    p.send_payload(&payload).await.unwrap();
+    let err = p.receive_payload().await.unwrap_err();
+    assert_eq!(err.to_string(), "worker error: injected error on the job 2");
+}
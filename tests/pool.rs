@@ -0,0 +1,428 @@
+mod common;
+
+use common::{eventually, frame};
+use goridge_rs::pool::{Config, ExecOptions, Pool};
+use goridge_rs::worker::{Backoff, CircuitBreaker, Spec};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+
+// directory the workers started with --start-gate register in, held on the start until opened
+struct Gate(PathBuf);
+
+impl Gate {
+    fn new(name: &str) -> Self {
+        let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Gate(dir)
+    }
+
+    fn spec(&self, args: &[&str]) -> Spec {
+        let mut cmd = vec![WORKER, "--start-gate", self.0.to_str().unwrap()];
+        cmd.extend_from_slice(args);
+        Spec::new(&cmd)
+    }
+
+    // PIDs of the started workers
+    fn started(&self) -> Vec<String> {
+        let mut pids: Vec<String> = std::fs::read_dir(&self.0)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.starts_with("open"))
+            .collect();
+        pids.sort();
+        pids
+    }
+
+    async fn wait_started(&self, n: usize) -> Vec<String> {
+        eventually(|| self.started().len() >= n).await;
+        self.started()
+    }
+
+    fn open(&self, pid: &str) {
+        std::fs::write(self.0.join(format!("open-{}", pid)), b"").unwrap();
+    }
+
+    fn open_all(&self) {
+        std::fs::write(self.0.join("open"), b"").unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_parallel_allocation() {
+    // every worker is held on the start until all of them are spawned, one by one never ends
+    let gate = Gate::new("pool-parallel");
+    let mut config = Config::new(gate.spec(&[]), 8);
+    config.set_max_concurrent_spawns(8);
+    let pool = Pool::new(config);
+
+    gate.wait_started(8).await;
+    gate.open_all();
+    pool.wait_ready().await.unwrap();
+    assert!(pool.is_ready());
+    assert_eq!(pool.idle_workers(), 8);
+
+    pool.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_bounded_concurrency() {
+    let gate = Gate::new("pool-bounded");
+    let mut config = Config::new(gate.spec(&[]), 4);
+    config.set_max_concurrent_spawns(1);
+    let pool = Pool::new(config);
+
+    // the next spawn waits for the worker held on the start
+    for n in 1..=4 {
+        let started = gate.wait_started(n).await;
+        assert_eq!(started.len(), n);
+        for pid in &started {
+            gate.open(pid);
+        }
+    }
+
+    pool.wait_ready().await.unwrap();
+    assert_eq!(pool.idle_workers(), 4);
+}
+
+#[tokio::test]
+async fn test_min_ready() {
+    let gate = Gate::new("pool-min-ready");
+    let mut config = Config::new(gate.spec(&[]), 4);
+    config.set_min_ready(2);
+    let pool = Pool::new(config);
+
+    let started = gate.wait_started(4).await;
+    gate.open(&started[0]);
+    gate.open(&started[1]);
+
+    pool.wait_ready().await.unwrap();
+    assert!(pool.is_ready());
+    // the other two are still held on the start
+    assert_eq!(pool.idle_workers(), 2);
+
+    // the rest are allocated in the background
+    let response = pool.exec(&mut frame(b"hello")).await.unwrap();
+    assert_eq!(response.payload(), b"hello");
+    gate.open_all();
+    eventually(|| pool.idle_workers() == 4).await;
+}
+
+#[tokio::test]
+async fn test_allocate_timeout() {
+    let mut config = Config::new(Spec::new(&[WORKER, "--start-delay-ms", "5000"]), 2);
+    config.set_allocate_timeout(Duration::from_millis(100));
+
+    let err = Pool::start(config).await.err().unwrap().to_string();
+    assert!(err.starts_with("pool is not ready: "));
+    assert!(err.contains("of 2 workers failed to allocate, 2 required"));
+    assert!(err.contains("worker allocation timed out after 100ms"));
+}
+
+#[tokio::test]
+async fn test_allocation_retried() {
+    let marker = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("pool-crash-once");
+    _ = std::fs::remove_file(&marker);
+
+    // the first spawn crashes, the retry succeeds
+    let spec = Spec::new(&[WORKER, "--crash-once", marker.to_str().unwrap()]);
+    let mut config = Config::new(spec, 2);
+    config.set_backoff(Backoff {
+        initial: Duration::from_millis(10),
+        ..Default::default()
+    });
+
+    let pool = Pool::start(config).await.unwrap();
+    assert!(pool.is_ready());
+    assert_eq!(pool.idle_workers(), 2);
+}
+
+#[tokio::test]
+async fn test_concurrent_exec() {
+    let pool = Arc::new(
+        Pool::start(Config::new(Spec::new(&[WORKER, "--delay-ms", "10"]), 2))
+            .await
+            .unwrap(),
+    );
+
+    let mut jobs = tokio::task::JoinSet::new();
+    for i in 0..20 {
+        let pool = pool.clone();
+        jobs.spawn(async move {
+            let payload = format!("job {}", i);
+            let response = pool.exec(&mut frame(payload.as_bytes())).await.unwrap();
+            assert_eq!(response.payload(), payload.as_bytes());
+        });
+    }
+    while let Some(job) = jobs.join_next().await {
+        job.unwrap();
+    }
+
+    assert_eq!(pool.idle_workers(), 2);
+    pool.stop().await.unwrap();
+    assert!(pool.exec(&mut frame(b"hello")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_replace_crashed_worker() {
+    let pool = Pool::start(Config::new(Spec::new(&[WORKER, "--exit-after", "2"]), 1))
+        .await
+        .unwrap();
+
+    pool.exec(&mut frame(b"hello")).await.unwrap();
+    assert!(pool.exec(&mut frame(b"hello")).await.is_err());
+
+    // waits for the replacement
+    let response = pool.exec(&mut frame(b"world")).await.unwrap();
+    assert_eq!(response.payload(), b"world");
+}
+
+#[tokio::test]
+async fn test_respawn_crash_loop() {
+    let marker = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("pool-crash-loop");
+    _ = std::fs::remove_file(&marker);
+
+    // the first worker dies on the job, every respawn crashes on the start
+    let spec = Spec::new(&[
+        WORKER,
+        "--exit-after",
+        "1",
+        "--crash-marker",
+        marker.to_str().unwrap(),
+    ]);
+    let mut config = Config::new(spec, 1);
+    config.set_backoff(Backoff {
+        initial: Duration::from_millis(10),
+        ..Default::default()
+    });
+    config.set_circuit_breaker(CircuitBreaker {
+        max_crashes: 3,
+        window: Duration::from_secs(10),
+        cooldown: Duration::from_secs(60),
+    });
+    let pool = Pool::start(config).await.unwrap();
+
+    assert!(pool.exec(&mut frame(b"hello")).await.is_err());
+
+    // the waiting exec fails once the respawns keep failing
+    let mut job = frame(b"hello");
+    let exec = tokio::time::timeout(Duration::from_secs(5), pool.exec(&mut job));
+    let err = exec.await.unwrap().unwrap_err().to_string();
+    assert!(
+        err.starts_with("no worker is available, circuit breaker is open: 3 failed allocations")
+    );
+    assert!(err.contains("exited with code 1"));
+
+    // no waiting until the cooldown ends
+    let exec = tokio::time::timeout(Duration::from_secs(5), pool.exec(&mut job));
+    assert!(exec.await.unwrap().is_err());
+    assert_eq!(pool.workers(), 0);
+}
+
+#[tokio::test]
+async fn test_reset() {
+    let pool = Arc::new(
+        Pool::start(Config::new(Spec::new(&[WORKER, "--delay-ms", "300"]), 2))
+            .await
+            .unwrap(),
+    );
+
+    // in flight during the reset
+    let busy = pool.clone();
+    let job = tokio::spawn(async move { busy.exec(&mut frame(b"in flight")).await });
+    eventually(|| pool.idle_workers() == 1).await;
+
+    let reset = pool.clone();
+    let reset = tokio::spawn(async move { reset.reset().await });
+
+    // the capacity is kept while the old worker drains
+    eventually(|| pool.generation() == 1).await;
+    assert_eq!(pool.idle_workers(), 2);
+    let response = pool.exec(&mut frame(b"hello")).await.unwrap();
+    assert_eq!(response.payload(), b"hello");
+    // the old worker is stopped by the job release
+    assert!(!reset.is_finished() || job.is_finished());
+
+    // the job is not dropped, the reset waits for it
+    let response = job.await.unwrap().unwrap();
+    assert_eq!(response.payload(), b"in flight");
+    reset.await.unwrap().unwrap();
+    assert_eq!(pool.idle_workers(), 2);
+
+    pool.reset().await.unwrap();
+    assert_eq!(pool.generation(), 2);
+    assert_eq!(pool.idle_workers(), 2);
+}
+
+#[tokio::test]
+async fn test_reset_stopped_pool() {
+    let pool = Pool::start(Config::new(Spec::new(&[WORKER]), 1))
+        .await
+        .unwrap();
+    pool.stop().await.unwrap();
+
+    assert!(pool.reset().await.is_err());
+    assert_eq!(pool.generation(), 0);
+}
+
+fn dynamic_config(spec: Spec) -> Config {
+    let mut config = Config::new(spec, 1);
+    config.set_max_workers(3);
+    config.set_queue_wait(Duration::from_millis(50));
+    config.set_idle_timeout(Duration::from_millis(500));
+    config.set_spawn_rate(10);
+    config
+}
+
+#[tokio::test]
+async fn test_scale_up_and_down() {
+    let spec = Spec::new(&[WORKER, "--delay-ms", "1000"]);
+    let pool = Arc::new(Pool::start(dynamic_config(spec)).await.unwrap());
+
+    let mut jobs = tokio::task::JoinSet::new();
+    for _ in 0..3 {
+        let pool = pool.clone();
+        jobs.spawn(async move { pool.exec(&mut frame(b"hello")).await });
+    }
+
+    // the waiting jobs got the extra workers
+    eventually(|| pool.workers() == 3).await;
+    while let Some(job) = jobs.join_next().await {
+        job.unwrap().unwrap();
+    }
+
+    // the extra workers are reaped after the idle timeout
+    eventually(|| pool.workers() == 1).await;
+    assert_eq!(pool.idle_workers(), 1);
+}
+
+#[tokio::test]
+async fn test_scale_spawn_rate() {
+    // the extra workers are held on the start, every spawn stays counted
+    let gate = Gate::new("pool-spawn-rate");
+    let mut config = dynamic_config(gate.spec(&["--delay-ms", "1000"]));
+    config.set_max_workers(5);
+    config.set_spawn_rate(1);
+    let pool = Arc::new(Pool::new(config));
+
+    let started = gate.wait_started(1).await;
+    gate.open(&started[0]);
+    pool.wait_ready().await.unwrap();
+
+    let mut jobs = tokio::task::JoinSet::new();
+    for _ in 0..5 {
+        let pool = pool.clone();
+        jobs.spawn(async move { pool.exec(&mut frame(b"hello")).await });
+    }
+
+    // a single extra worker per second
+    gate.wait_started(2).await;
+    assert_eq!(pool.workers(), 2);
+
+    jobs.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_static_pool_not_scaled() {
+    let mut config = dynamic_config(Spec::new(&[WORKER, "--delay-ms", "200"]));
+    config.set_max_workers(0);
+    let pool = Arc::new(Pool::start(config).await.unwrap());
+
+    let mut jobs = tokio::task::JoinSet::new();
+    for _ in 0..3 {
+        let pool = pool.clone();
+        jobs.spawn(async move { pool.exec(&mut frame(b"hello")).await });
+    }
+    while let Some(job) = jobs.join_next().await {
+        job.unwrap().unwrap();
+    }
+    assert_eq!(pool.workers(), 1);
+}
+
+#[tokio::test]
+async fn test_priority_and_tenants() {
+    // the only worker is held on the start while the jobs are queued
+    let gate = Gate::new("pool-priority");
+    let pool = Arc::new(Pool::new(Config::new(gate.spec(&[]), 1)));
+    let order = Arc::new(Mutex::new(vec![]));
+
+    let mut urgent = ExecOptions::new("b");
+    urgent.set_priority(10);
+    let queued = [
+        ("a0", ExecOptions::new("a")),
+        ("a1", ExecOptions::new("a")),
+        ("a2", ExecOptions::new("a")),
+        ("a3", ExecOptions::new("a")),
+        ("b0", ExecOptions::new("b")),
+        ("urgent", urgent),
+    ];
+
+    let mut jobs = tokio::task::JoinSet::new();
+    for (i, (name, options)) in queued.into_iter().enumerate() {
+        let (queue, order) = (pool.clone(), order.clone());
+        jobs.spawn(async move {
+            queue
+                .exec_with(&mut frame(name.as_bytes()), &options)
+                .await
+                .unwrap();
+            order.lock().unwrap().push(name);
+        });
+        eventually(|| pool.waiting_jobs() == i + 1).await;
+    }
+
+    gate.open_all();
+    while let Some(job) = jobs.join_next().await {
+        job.unwrap();
+    }
+
+    // the flood of the tenant a doesn't delay the tenant b
+    assert_eq!(
+        *order.lock().unwrap(),
+        ["urgent", "a0", "b0", "a1", "a2", "a3"]
+    );
+    assert_eq!(pool.waiting_jobs(), 0);
+}
+
+#[tokio::test]
+async fn test_cancel_dropped_exec() {
+    // the replacement would be held on the start, only the first worker is let through
+    let gate = Gate::new("pool-cancel");
+    let spec = gate.spec(&["--delay-ms", "60000", "--delay-jobs", "1"]);
+    let pool = Pool::new(Config::new(spec, 1));
+    let started = gate.wait_started(1).await;
+    gate.open(&started[0]);
+    pool.wait_ready().await.unwrap();
+
+    let mut job = frame(b"slow");
+
+    let exec = tokio::time::timeout(Duration::from_millis(100), pool.exec(&mut job));
+    assert!(exec.await.is_err());
+
+    // the cancelled worker is back long before the slow job would end
+    let mut job = frame(b"next");
+    let exec = tokio::time::timeout(Duration::from_secs(10), pool.exec(&mut job));
+    let response = exec.await.unwrap().unwrap();
+    assert_eq!(response.payload(), b"next");
+    assert_eq!(gate.started(), started);
+}
+
+#[tokio::test]
+async fn test_recycle_not_acknowledged() {
+    let spec = Spec::new(&[WORKER, "--delay-ms", "300", "--legacy-control", "1"]);
+    let mut config = Config::new(spec, 1);
+    config.set_cancel_timeout(Duration::from_millis(100));
+    let pool = Pool::start(config).await.unwrap();
+
+    let mut job = frame(b"slow");
+
+    let exec = tokio::time::timeout(Duration::from_millis(50), pool.exec(&mut job));
+    assert!(exec.await.is_err());
+
+    // the worker is replaced, the orphaned response doesn't reach the next job
+    let response = pool.exec(&mut frame(b"next")).await.unwrap();
+    assert_eq!(response.payload(), b"next");
+    assert_eq!(pool.workers(), 1);
+}
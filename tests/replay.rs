@@ -0,0 +1,98 @@
+mod common;
+
+use common::frame;
+use goridge_rs::pipe::Pipes;
+use goridge_rs::pipe::commands::StopCommand;
+use goridge_rs::replay::{Difference, load, replay};
+use std::path::PathBuf;
+use std::process::Command;
+
+const TAP: &str = env!("CARGO_BIN_EXE_goridge-tap");
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+const REPLAY: &str = env!("CARGO_BIN_EXE_goridge-replay");
+
+// records the traffic of the echo worker
+async fn record(name: &str, jobs: usize) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.grcap", name));
+    let mut p = Pipes::new(&[TAP, "--capture", path.to_str().unwrap(), "--", WORKER])
+        .await
+        .unwrap();
+    p.send_pid().await.unwrap();
+
+    for i in 0..jobs {
+        p.send(&mut frame(format!("hello {}", i).as_bytes()))
+            .await
+            .unwrap();
+        p.receive_stdout().await.unwrap();
+    }
+
+    p.send_control(StopCommand::default()).await.unwrap();
+    p.wait().await.unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_replay_capture() {
+    let path = record("replay_capture", 10).await;
+    let exchanges = load(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(exchanges.len(), 10);
+
+    let mut p = Pipes::new(&[WORKER, "--error-every", "5"]).await.unwrap();
+    p.send_pid().await.unwrap();
+
+    let report = replay(&mut p, &exchanges).await.unwrap();
+    assert_eq!(report.results.len(), 10);
+    assert_eq!(report.mismatches(), 2);
+    assert!(report.latency_percentile(0.99).is_some());
+    assert!(report.recorded_latency_percentile(0.99).is_some());
+
+    let differences = &report.results[4].differences;
+    assert!(matches!(differences[0], Difference::Flags { .. }));
+    assert_eq!(
+        report.results[4].response.payload(),
+        b"injected error on the job 5"
+    );
+}
+
+#[tokio::test]
+async fn test_replay_requests_file() {
+    let mut data = frame(b"hello").bytes();
+    data.extend(frame(b"world").bytes());
+    let exchanges = load(&data).unwrap();
+
+    let mut p = Pipes::new(&[WORKER]).await.unwrap();
+    p.send_pid().await.unwrap();
+
+    // nothing recorded, nothing to compare with
+    let report = replay(&mut p, &exchanges).await.unwrap();
+    assert_eq!(report.mismatches(), 0);
+    assert_eq!(report.results[1].response.payload(), b"world");
+    assert!(report.recorded_latency_percentile(0.5).is_none());
+}
+
+#[tokio::test]
+async fn test_replay_cli() {
+    let path = record("replay_cli", 4).await;
+
+    let out = Command::new(REPLAY)
+        .args([path.to_str().unwrap(), "--", WORKER])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert!(out.status.success());
+    assert!(stdout.contains("4 exchanges, 0 mismatches"));
+    assert!(stdout.contains("latency p99: "));
+
+    let out = Command::new(REPLAY)
+        .args([path.to_str().unwrap(), "--", WORKER, "--error-every", "2"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    assert!(stdout.contains("exchange #1: "));
+    assert!(stdout.contains("  flags: expected Flags(CODEC_RAW), got Flags(ERROR)"));
+    assert!(stdout.contains("  payload differs at byte 0: expected 7 bytes, got 27 bytes"));
+    assert!(stdout.contains("4 exchanges, 2 mismatches"));
+}
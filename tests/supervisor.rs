@@ -0,0 +1,128 @@
+mod common;
+
+use common::{eventually, frame};
+use goridge_rs::worker::{Backoff, BreakerState, CircuitBreaker, Spec, State, Supervisor};
+use std::time::{Duration, Instant};
+
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+
+#[tokio::test]
+async fn test_respawn_after_crash() {
+    let mut s = Supervisor::new(Spec::new(&[WORKER, "--exit-after", "3"]));
+
+    s.exec(&mut frame(b"hello")).await.unwrap();
+    let pid = s.worker().unwrap().pid();
+    s.exec(&mut frame(b"hello")).await.unwrap();
+
+    // the job the worker crashed on is failed
+    assert!(s.exec(&mut frame(b"hello")).await.is_err());
+    assert!(s.worker().is_none());
+
+    let response = s.exec(&mut frame(b"world")).await.unwrap();
+    assert_eq!(response.payload(), b"world");
+    assert_ne!(s.worker().unwrap().pid(), pid);
+    assert_eq!(s.restarts(), 1);
+    assert_eq!(s.breaker_state(), BreakerState::Closed);
+
+    s.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_respawn_idle_worker() {
+    let mut s = Supervisor::new(Spec::new(&[WORKER]));
+    s.ensure_worker().await.unwrap();
+
+    // died between the jobs, replaced before the job is sent
+    s.worker_mut().unwrap().pipes_mut().kill().await.unwrap();
+    s.exec(&mut frame(b"hello")).await.unwrap();
+    assert_eq!(s.restarts(), 1);
+
+    // taken out of service, replaced as well
+    s.worker_mut().unwrap().set_state(State::Invalid).unwrap();
+    s.exec(&mut frame(b"hello")).await.unwrap();
+    assert_eq!(s.restarts(), 2);
+}
+
+#[tokio::test]
+async fn test_circuit_breaker() {
+    // crashes on every job
+    let mut s = Supervisor::new(Spec::new(&[WORKER, "--exit-after", "1"]));
+    s.set_backoff(Backoff {
+        initial: Duration::from_millis(50),
+        jitter: 0.0,
+        ..Default::default()
+    });
+    s.set_circuit_breaker(CircuitBreaker {
+        max_crashes: 3,
+        window: Duration::from_secs(10),
+        cooldown: Duration::from_millis(300),
+    });
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        assert!(s.exec(&mut frame(b"hello")).await.is_err());
+    }
+    // the first respawn is immediate, the second is delayed
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(s.breaker_state(), BreakerState::Open);
+
+    let err = s.exec(&mut frame(b"hello")).await.unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("circuit breaker is open: 3 crashes")
+    );
+    // the crash loop cause is reported with the breaker error
+    assert_eq!(s.last_exit().unwrap().code, Some(0));
+    assert!(err.to_string().contains("last crash: worker"));
+    assert_eq!(s.restarts(), 2);
+
+    eventually(|| s.breaker_state() == BreakerState::HalfOpen).await;
+
+    // the single attempt, opened again by the crash
+    assert!(s.exec(&mut frame(b"hello")).await.is_err());
+    assert_eq!(s.restarts(), 3);
+    assert_eq!(s.breaker_state(), BreakerState::Open);
+}
+
+#[tokio::test]
+async fn test_half_open_recovery() {
+    let mut s = Supervisor::new(Spec::new(&[WORKER, "--exit-after", "2"]));
+    s.set_circuit_breaker(CircuitBreaker {
+        max_crashes: 1,
+        window: Duration::from_secs(10),
+        cooldown: Duration::from_millis(100),
+    });
+
+    s.exec(&mut frame(b"hello")).await.unwrap();
+    assert!(s.exec(&mut frame(b"hello")).await.is_err());
+    assert_eq!(s.breaker_state(), BreakerState::Open);
+
+    eventually(|| s.breaker_state() == BreakerState::HalfOpen).await;
+    s.exec(&mut frame(b"hello")).await.unwrap();
+    assert_eq!(s.breaker_state(), BreakerState::Closed);
+}
+
+#[tokio::test]
+async fn test_spawn_failure() {
+    let mut s = Supervisor::new(Spec::new(&["/nonexistent/worker"]));
+    assert!(s.exec(&mut frame(b"hello")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_cancel_dropped_exec() {
+    let spec = Spec::new(&[WORKER, "--delay-ms", "60000", "--delay-jobs", "1"]);
+    let mut s = Supervisor::new(spec);
+    s.ensure_worker().await.unwrap();
+
+    let mut job = frame(b"slow");
+
+    let exec = tokio::time::timeout(Duration::from_millis(50), s.exec(&mut job));
+    assert!(exec.await.is_err());
+
+    // cancelled, not counted as the crash
+    let mut job = frame(b"next");
+    let exec = tokio::time::timeout(Duration::from_secs(10), s.exec(&mut job));
+    let response = exec.await.unwrap().unwrap();
+    assert_eq!(response.payload(), b"next");
+    assert_eq!(s.restarts(), 0);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use common::frame;
+use goridge_rs::capture::{CaptureReader, Direction};
+use goridge_rs::frame::frame_flags::Flags;
+use goridge_rs::pipe::Pipes;
+use goridge_rs::pipe::commands::StopCommand;
+use std::path::PathBuf;
+use std::process::Command;
+
+const TAP: &str = env!("CARGO_BIN_EXE_goridge-tap");
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+const DUMP: &str = env!("CARGO_BIN_EXE_goridge-dump");
+
+fn capture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.grcap", name))
+}
+
+#[tokio::test]
+async fn test_tap_records_both_directions() {
+    let path = capture_path("tap_records");
+    let mut p = Pipes::new(&[TAP, "--capture", path.to_str().unwrap(), "--", WORKER])
+        .await
+        .unwrap();
+
+    // the tap is transparent, the PID is the worker one
+    assert_ne!(p.send_pid().await.unwrap(), p.id().await.unwrap());
+
+    for i in 0..3 {
+        let payload = format!("hello {}", i);
+        p.send(&mut frame(payload.as_bytes())).await.unwrap();
+        assert_eq!(
+            p.receive_stdout().await.unwrap().payload(),
+            payload.as_bytes()
+        );
+    }
+
+    p.send_control(StopCommand::default()).await.unwrap();
+    p.wait().await.unwrap();
+    assert!(p.try_wait().await.unwrap().unwrap().success());
+
+    let reader = CaptureReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let records: Vec<_> = reader.map(|r| r.unwrap()).collect();
+
+    // pid request and response, 3 jobs, stop
+    assert_eq!(records.len(), 9);
+    assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+    let to_worker: Vec<_> = records
+        .iter()
+        .filter(|r| r.direction == Direction::ToWorker)
+        .map(|r| r.frame().unwrap())
+        .collect();
+    let from_worker: Vec<_> = records
+        .iter()
+        .filter(|r| r.direction == Direction::FromWorker)
+        .map(|r| r.frame().unwrap())
+        .collect();
+
+    assert_eq!(to_worker.len(), 5);
+    assert_eq!(from_worker.len(), 4);
+    assert!(to_worker[0].read_flags().contains(Flags::CONTROL));
+    assert!(to_worker[4].read_flags().contains(Flags::CONTROL));
+    assert_eq!(to_worker[2].payload(), b"hello 1");
+    assert_eq!(from_worker[3].payload(), b"hello 2");
+
+    let out = Command::new(DUMP)
+        .args(["--capture", path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert!(out.status.success());
+    assert!(stdout.starts_with("capture started at "));
+    assert!(stdout.contains("record #0 at +0.000000s: parent -> worker"));
+    assert!(stdout.contains("record #1 at +"));
+    assert!(stdout.contains("payload: \"hello 2\""));
+    assert!(stdout.ends_with("9 frames (5 parent -> worker, 4 worker -> parent)\n"));
+}
+
+#[tokio::test]
+async fn test_tap_noise_and_exit_code() {
+    let path = capture_path("tap_noise");
+    let mut p = Pipes::new(&[
+        TAP,
+        "--capture",
+        path.to_str().unwrap(),
+        "--",
+        WORKER,
+        "--noise-every",
+        "2",
+        "--exit-after",
+        "3",
+        "--exit-code",
+        "3",
+    ])
+    .await
+    .unwrap();
+
+    p.send(&mut frame(b"hello")).await.unwrap();
+    assert!(p.receive_stdout().await.is_ok());
+    p.send(&mut frame(b"hello")).await.unwrap();
+    // noise is forwarded unchanged
+    assert!(p.receive_stdout().await.is_err());
+    p.send(&mut frame(b"hello")).await.unwrap();
+
+    p.wait().await.unwrap();
+    assert_eq!(p.try_wait().await.unwrap().unwrap().code(), Some(3));
+
+    let reader = CaptureReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let from_worker: Vec<_> = reader
+        .map(|r| r.unwrap())
+        .filter(|r| r.direction == Direction::FromWorker)
+        .collect();
+
+    // the noise could be read in parts, it is recorded between the responses
+    assert!(from_worker.len() >= 3);
+    assert!(from_worker[0].frame().is_some());
+    assert!(from_worker.last().unwrap().frame().is_some());
+    assert!(
+        from_worker[1..from_worker.len() - 1]
+            .iter()
+            .all(|r| r.frame().is_none())
+    );
+
+    let out = Command::new(DUMP)
+        .args(["--capture", path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    assert!(stdout.contains("worker -> parent"));
+    assert!(stdout.contains("corrupted data at offset 17"));
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use common::frame;
+use goridge_rs::pipe::Pipes;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt::format::FmtSpan;
+
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_spans() {
+    let output = Output::default();
+    let writer = output.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let mut p = Pipes::new(&[WORKER]).await.unwrap();
+    let pid = p.send_pid().await.unwrap();
+
+    let mut frame = frame(b"hello");
+
+    p.send(&mut frame).await.unwrap();
+    p.receive_stdout().await.unwrap();
+
+    let logs = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    let span = |name: &str| {
+        logs.lines()
+            .find(|l| l.contains(&format!("{}{{", name)))
+            .unwrap_or_else(|| panic!("no {} span in:\n{}", name, logs))
+            .to_string()
+    };
+
+    let send_pid = span("pipe.send_pid");
+    assert!(send_pid.contains(&format!("pid={}", pid)));
+    assert!(send_pid.contains("flags=Flags(CONTROL | CODEC_JSON)"));
+    assert!(send_pid.contains("version=1"));
+
+    let send = span("pipe.send");
+    assert!(send.contains("flags=Flags(CODEC_RAW)"));
+    assert!(send.contains("payload_len=5"));
+    assert!(send.contains("crc=\"valid\""));
+    assert!(send.contains("elapsed_us="));
+
+    let receive = span("pipe.receive_stdout");
+    assert!(receive.contains("payload_len=5"));
+    assert!(receive.contains("crc=\"valid\""));
+}
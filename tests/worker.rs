@@ -0,0 +1,231 @@
+mod common;
+
+use common::frame;
+use goridge_rs::frame::integrity::Integrity;
+use goridge_rs::pipe::Pipes;
+use goridge_rs::worker::{Spec, State, Worker, WorkerExit};
+use std::time::Duration;
+
+const WORKER: &str = env!("CARGO_BIN_EXE_goridge-echo-worker");
+
+#[tokio::test]
+async fn test_worker_lifecycle() {
+    let mut w = Worker::new(Pipes::new(&[WORKER]).await.unwrap());
+    let mut rx = w.subscribe();
+    assert_eq!(w.state(), State::Inactive);
+    assert!(w.exec(&mut frame(b"hello")).await.is_err());
+
+    w.start().await.unwrap();
+    assert_eq!(w.state(), State::Ready);
+    assert_eq!(w.pid(), w.pipes_mut().id().await.unwrap());
+    assert!(w.start().await.is_err());
+
+    rx.changed().await.unwrap();
+    assert_eq!(rx.borrow_and_update().state, State::Ready);
+
+    let response = w.exec(&mut frame(b"hello")).await.unwrap();
+    assert_eq!(response.payload(), b"hello");
+    assert_eq!(w.jobs(), 1);
+    assert_eq!(w.state(), State::Ready);
+    assert_eq!(w.event().previous, Some(State::Working));
+
+    w.set_state(State::Invalid).unwrap();
+    assert!(w.exec(&mut frame(b"hello")).await.is_err());
+
+    w.stop().await.unwrap();
+    assert_eq!(w.state(), State::Stopped);
+    w.destroy().await.unwrap();
+    assert_eq!(w.state(), State::Destroyed);
+
+    let event = *rx.borrow_and_update();
+    assert_eq!(event.state, State::Destroyed);
+    assert_eq!(event.seq, 7);
+}
+
+#[tokio::test]
+async fn test_worker_crash() {
+    let mut w = Worker::spawn(&[WORKER, "--exit-after", "2"]).await.unwrap();
+
+    w.exec(&mut frame(b"hello")).await.unwrap();
+    assert!(w.exec(&mut frame(b"hello")).await.is_err());
+    assert_eq!(w.state(), State::Errored);
+    assert_eq!(w.jobs(), 1);
+
+    assert!(w.stop().await.is_err());
+    w.destroy().await.unwrap();
+    assert_eq!(w.state(), State::Destroyed);
+}
+
+#[tokio::test]
+async fn test_worker_stop_timeout() {
+    // busy with the job, the stop command is read after the delay
+    let mut w = Worker::spawn(&[WORKER, "--delay-ms", "5000"])
+        .await
+        .unwrap();
+    w.set_stop_timeout(Duration::from_millis(100));
+
+    w.pipes_mut().send(&mut frame(b"hello")).await.unwrap();
+    let err = w.stop().await.unwrap_err();
+    assert!(err.to_string().contains("did not stop"));
+    assert_eq!(w.state(), State::Destroyed);
+}
+
+#[tokio::test]
+async fn test_worker_exit_report() {
+    let mut w = Worker::spawn(&[
+        WORKER,
+        "--exit-after",
+        "3",
+        "--exit-code",
+        "255",
+        "--exit-stderr",
+        "30",
+    ])
+    .await
+    .unwrap();
+    w.set_stderr_lines(5);
+    let pid = w.pid();
+
+    w.exec(&mut frame(b"hello")).await.unwrap();
+    w.exec(&mut frame(b"hello")).await.unwrap();
+    let err = w.exec(&mut frame(b"hello")).await.unwrap_err();
+
+    let exit = err.downcast_ref::<WorkerExit>().unwrap();
+    assert_eq!(exit.pid, pid);
+    assert_eq!(exit.code, Some(255));
+    assert_eq!(exit.signal, None);
+    assert_eq!(exit.jobs, 2);
+    assert_eq!(exit.stderr.len(), 5);
+    assert_eq!(exit.stderr[0], "fatal error 26 on the job 3");
+    assert_eq!(exit.stderr[4], "fatal error 30 on the job 3");
+    assert!(err.to_string().contains("exited with code 255"));
+    assert_eq!(w.exit().unwrap().stderr, exit.stderr);
+}
+
+#[tokio::test]
+async fn test_worker_killed_report() {
+    let mut w = Worker::spawn(&[WORKER]).await.unwrap();
+    assert!(w.exit().is_none());
+
+    // killed from the outside, e.g. by the OOM killer
+    let status = std::process::Command::new("kill")
+        .args(["-9", &w.pid().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let err = w.exec(&mut frame(b"hello")).await.unwrap_err();
+    let exit = err.downcast_ref::<WorkerExit>().unwrap();
+    assert_eq!(exit.code, None);
+    assert_eq!(exit.signal, Some(9));
+    assert!(exit.stderr.is_empty());
+    assert!(err.to_string().contains("killed by signal 9 (SIGKILL)"));
+}
+
+#[tokio::test]
+async fn test_worker_error_alive() {
+    let mut w = Worker::spawn(&[WORKER, "--noise-every", "1"])
+        .await
+        .unwrap();
+    w.pipes_mut().set_integrity(Integrity::Header);
+
+    // the garbage read timeout and the exit grace run on the paused clock
+    tokio::time::pause();
+    let start = tokio::time::Instant::now();
+    let err = w.exec(&mut frame(b"hello")).await.unwrap_err();
+
+    assert!(err.downcast_ref::<WorkerExit>().is_none());
+    assert!(w.pipes_mut().try_wait().await.unwrap().is_none());
+    // only the 2s garbage read timeout, not the 100ms grace given to the exiting worker
+    assert!(start.elapsed() < Duration::from_millis(2050));
+}
+
+#[tokio::test]
+async fn test_worker_handshake_exit() {
+    // fails before the handshake, like a worker with a syntax error
+    let mut spec = Spec::new(&[WORKER, "--unknown", "1"]);
+    spec.set_env("RUST_BACKTRACE", "0");
+    spec.set_env("RUST_LIB_BACKTRACE", "0");
+    let err = Worker::from_spec(&spec).await.err().unwrap();
+
+    let exit = err.downcast_ref::<WorkerExit>().unwrap();
+    assert_eq!(exit.code, Some(1));
+    assert_eq!(exit.jobs, 0);
+    assert!(
+        exit.stderr
+            .iter()
+            .any(|l| l.contains("unknown argument: --unknown"))
+    );
+}
+
+#[tokio::test]
+async fn test_worker_cancel() {
+    let mut w = Worker::spawn(&[WORKER, "--delay-ms", "60000"])
+        .await
+        .unwrap();
+
+    // the client is gone, the exec is dropped
+    let mut job = frame(b"slow");
+
+    let exec = tokio::time::timeout(Duration::from_millis(100), w.exec(&mut job));
+    assert!(exec.await.is_err());
+    assert_eq!(w.state(), State::Working);
+
+    // aborted by the worker, doesn't run to the end
+    let cancel = tokio::time::timeout(Duration::from_secs(10), w.cancel());
+    cancel.await.unwrap().unwrap();
+    assert_eq!(w.state(), State::Ready);
+    assert!(w.cancel().await.is_err());
+
+    // no orphaned response in the pipe
+    let mut w = Worker::spawn(&[WORKER, "--delay-ms", "100"]).await.unwrap();
+    let mut job = frame(b"slow");
+
+    let exec = tokio::time::timeout(Duration::from_millis(50), w.exec(&mut job));
+    assert!(exec.await.is_err());
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    w.cancel().await.unwrap();
+    let response = w.exec(&mut frame(b"fresh")).await.unwrap();
+    assert_eq!(response.payload(), b"fresh");
+}
+
+#[tokio::test]
+async fn test_worker_cancel_not_acknowledged() {
+    let mut w = Worker::spawn(&[WORKER, "--delay-ms", "60000", "--legacy-control", "1"])
+        .await
+        .unwrap();
+    w.set_cancel_timeout(Duration::from_millis(100));
+
+    let mut job = frame(b"slow");
+
+    let exec = tokio::time::timeout(Duration::from_millis(50), w.exec(&mut job));
+    assert!(exec.await.is_err());
+
+    let err = w.cancel().await.unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("did not acknowledge the cancel in 100ms")
+    );
+    assert_eq!(w.state(), State::Invalid);
+    w.destroy().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_worker_cancel_legacy_exits() {
+    let mut w = Worker::spawn(&[WORKER, "--delay-ms", "100", "--legacy-control", "1"])
+        .await
+        .unwrap();
+    w.set_cancel_timeout(Duration::from_secs(60));
+
+    let mut job = frame(b"slow");
+
+    let exec = tokio::time::timeout(Duration::from_millis(50), w.exec(&mut job));
+    assert!(exec.await.is_err());
+
+    // the unknown command fails the worker once the job is done, no waiting for the timeout
+    let cancel = tokio::time::timeout(Duration::from_secs(10), w.cancel());
+    assert!(cancel.await.unwrap().is_err());
+    assert_eq!(w.state(), State::Invalid);
+    w.destroy().await.unwrap();
+}